@@ -5,7 +5,20 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use feature::prelude::*;
 use rand::{Rng, SeedableRng};
 
-fn build_dag(nodes: usize, edges: usize) -> Dag<usize> {
+/// Edge label that mimics the dependency kinds of a workspace.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+	Normal,
+	Dev,
+}
+
+impl EdgeLabel for Kind {
+	fn first_hop_only(&self) -> bool {
+		*self == Self::Dev
+	}
+}
+
+fn build_dag(nodes: usize, edges: usize) -> Dag<usize, Kind> {
 	let mut rng = rand::rngs::StdRng::seed_from_u64(42);
 
 	let mut dag = Dag::default();
@@ -15,13 +28,15 @@ fn build_dag(nodes: usize, edges: usize) -> Dag<usize> {
 	for _ in 0..edges {
 		let from = rng.gen_range(0..nodes);
 		let to = rng.gen_range(0..nodes);
-		dag.add_edge(from, to);
+		// Every tenth dependency is a dev-dependency.
+		let kind = if rng.gen_range(0..10) == 0 { Kind::Dev } else { Kind::Normal };
+		dag.add_labeled_edge(from, to, kind);
 	}
 	dag
 }
 
-fn any_path(dag: &Dag<usize>) {
-	dag.any_path(&0, &1);
+fn any_path(dag: &Dag<usize, Kind>) -> bool {
+	dag.any_path(&0, &1).is_some()
 }
 
 fn criterion_benchmark(c: &mut Criterion) {
@@ -4,7 +4,7 @@
 //! Automatically fix problems by modifying `Cargo.toml` files.
 
 use std::path::{Path, PathBuf};
-use toml_edit::{table, value, Array, Document, Value};
+use toml_edit::{table, value, Array, Document, RawString, TableLike, Value};
 
 pub struct AutoFixer {
 	pub manifest: Option<PathBuf>,
@@ -47,23 +47,86 @@ impl AutoFixer {
 		feature.clear();
 
 		for value in values.into_iter() {
-			if value.as_str().map_or(false, |s| s.is_empty()) {
+			if value.as_str().is_some_and(|s| s.is_empty()) {
 				panic!("Empty value in feature");
 			}
-			let value = value.decorated("\n\t", "");
+			// Comments like `# feature: allow(..)` live in the decor and must survive.
+			let prefix = commented(value.decor().prefix()).unwrap_or("\n\t").to_string();
+			let suffix = commented(value.decor().suffix()).unwrap_or("").to_string();
+			let value = value.decorated(prefix, suffix);
 			feature.push_formatted(value);
 		}
 		if v.is_empty() {
 			panic!("Empty value in feature");
 		}
 		let mut value: Value = v.into();
-		// Working around `feature = []`.
-		value = value.decorated("\n\t", "\n");
+		// Working around `feature = []`, unless a trailing comment already closes the array.
+		let suffix = if commented(Some(feature.trailing())).is_some() {
+			""
+		} else {
+			feature.set_trailing("");
+			feature.set_trailing_comma(false);
+			"\n"
+		};
+		value = value.decorated("\n\t", suffix);
 		feature.push_formatted(value);
 
 		Ok(())
 	}
 
+	/// Remove the dependency `name` from all dependency tables and features.
+	///
+	/// Returns whether the manifest contained the dependency.
+	pub fn remove_dependency(&mut self, name: &str) -> Result<bool, String> {
+		let doc: &mut Document = self.doc.as_mut().unwrap();
+		let kinds = ["dependencies", "dev-dependencies", "build-dependencies"];
+		let mut removed = false;
+
+		removed |= remove_from_tables(doc.as_table_mut(), &kinds, name);
+		if let Some(targets) = doc.get_mut("target").and_then(|t| t.as_table_like_mut()) {
+			let mut emptied = Vec::new();
+			for (cfg, target) in targets.iter_mut() {
+				if let Some(target) = target.as_table_like_mut() {
+					if remove_from_tables(target, &kinds, name) {
+						removed = true;
+						if target.is_empty() {
+							emptied.push(cfg.get().to_string());
+						}
+					}
+				}
+			}
+			for cfg in emptied {
+				targets.remove(&cfg);
+			}
+			if targets.is_empty() {
+				doc.remove("target");
+			}
+		}
+		// Features must not reference dependencies that do not exist.
+		if let Some(features) = doc.get_mut("features").and_then(|f| f.as_table_like_mut()) {
+			for (_, feature) in features.iter_mut() {
+				if let Some(feature) = feature.as_array_mut() {
+					let references = |v: &str| {
+						v == format!("dep:{name}") ||
+							v.starts_with(&format!("{name}/")) ||
+							v.starts_with(&format!("{name}?/"))
+					};
+					let len = feature.len();
+					for i in (0..len).rev() {
+						if feature.get(i).and_then(|v| v.as_str()).is_some_and(references) {
+							feature.remove(i);
+						}
+					}
+					if feature.len() != len {
+						tidy(feature);
+					}
+				}
+			}
+		}
+
+		Ok(removed)
+	}
+
 	pub fn save(&mut self) -> Result<(), String> {
 		if let (Some(doc), Some(path)) = (self.doc.take(), &self.manifest) {
 			std::fs::write(path, doc.to_string())
@@ -74,9 +137,58 @@ impl AutoFixer {
 	}
 }
 
-impl ToString for AutoFixer {
-	fn to_string(&self) -> String {
-		self.doc.as_ref().unwrap().to_string()
+impl core::fmt::Display for AutoFixer {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		self.doc.as_ref().unwrap().fmt(f)
+	}
+}
+
+/// The raw decor if it contains a comment.
+fn commented(raw: Option<&RawString>) -> Option<&str> {
+	raw.and_then(RawString::as_str).filter(|raw| raw.contains('#'))
+}
+
+/// Remove `name` from the dependency tables `kinds` of `table` and drop the tables that become
+/// empty.
+fn remove_from_tables(table: &mut dyn TableLike, kinds: &[&str], name: &str) -> bool {
+	let mut removed = false;
+
+	for kind in kinds {
+		if let Some(deps) = table.get_mut(kind).and_then(|d| d.as_table_like_mut()) {
+			if deps.remove(name).is_some() {
+				removed = true;
+				if deps.is_empty() {
+					table.remove(kind);
+				}
+			}
+		}
+	}
+	removed
+}
+
+/// Re-align the elements of an array after some of them were removed.
+///
+/// Single-line arrays become `["a", "b"]` again and multi-line arrays keep their closing bracket
+/// on its own line.
+fn tidy(array: &mut Array) {
+	if array.is_empty() {
+		array.set_trailing("");
+		array.set_trailing_comma(false);
+		return
+	}
+	let newline =
+		|raw: Option<&RawString>| raw.and_then(RawString::as_str).is_some_and(|r| r.contains('\n'));
+	let multiline = array.iter().any(|v| newline(v.decor().prefix()));
+
+	if !multiline {
+		for (i, value) in array.iter_mut().enumerate() {
+			value.decor_mut().set_prefix(if i == 0 { "" } else { " " });
+		}
+	} else if !newline(Some(array.trailing())) {
+		let last = array.iter_mut().last().unwrap();
+		if !newline(last.decor().suffix()) {
+			last.decor_mut().set_suffix("\n");
+		}
 	}
 }
 
@@ -117,6 +229,75 @@ std = [
 		assert_eq!(fixer.to_string(), after);
 	}
 
+	#[test]
+	fn add_to_feature_weak_works() {
+		let before = r#"
+[dependencies]
+frame-support = { version = "1", optional = true }
+
+[features]
+std = ["frame-support?/std"]
+    "#;
+
+		let after = r#"
+[dependencies]
+frame-support = { version = "1", optional = true }
+
+[features]
+std = [
+	"frame-support?/std",
+	"frame-system?/std"
+]
+runtime-benchmarks = [
+	"frame-support?/runtime-benchmarks"
+]
+    "#;
+
+		let mut fixer = AutoFixer::from_raw(before).unwrap();
+		fixer
+			.add_to_feature("runtime-benchmarks", "frame-support?/runtime-benchmarks")
+			.unwrap();
+		fixer.add_to_feature("std", "frame-system?/std").unwrap();
+		assert_eq!(fixer.to_string(), after);
+	}
+
+	#[test]
+	fn remove_dependency_works() {
+		let before = r#"
+[dependencies]
+frame-support = { version = "1", optional = true }
+sp-std = "1"
+
+[target.'cfg(unix)'.dependencies]
+frame-support = "1"
+
+[features]
+std = ["frame-support?/std", "sp-std/std"]
+runtime-benchmarks = ["dep:frame-support"]
+try-runtime = [
+	"sp-std/try-runtime",
+	"frame-support?/try-runtime"
+]
+"#;
+
+		let after = r#"
+[dependencies]
+sp-std = "1"
+
+[features]
+std = ["sp-std/std"]
+runtime-benchmarks = []
+try-runtime = [
+	"sp-std/try-runtime"
+]
+"#;
+
+		let mut fixer = AutoFixer::from_raw(before).unwrap();
+		assert!(fixer.remove_dependency("frame-support").unwrap());
+		assert!(!fixer.remove_dependency("frame-system").unwrap());
+		assert_eq!(fixer.to_string(), after);
+	}
+
 	#[test]
 	fn crate_feature_works_without_section_exists() {
 		let before = r#""#;
@@ -138,4 +319,40 @@ std = [
 		let fixer = AutoFixer::from_raw(&raw).unwrap();
 		assert_eq!(fixer.to_string(), raw, "Formatting stays");
 	}
+
+	#[test]
+	fn add_to_feature_keeps_comments() {
+		let before = r#"
+[features]
+std = [
+	# feature: allow(sp-io)
+	"frame-support/std", # feature: allow(sp-core)
+	"sp-std/std",
+	# feature: allow(sp-runtime)
+]
+runtime-benchmarks = ["frame-support/runtime-benchmarks"] # feature: allow
+"#;
+
+		let after = r#"
+[features]
+std = [
+	# feature: allow(sp-io)
+	"frame-support/std", # feature: allow(sp-core)
+	"sp-std/std",
+	"frame-system/std",
+	# feature: allow(sp-runtime)
+]
+runtime-benchmarks = [
+	"frame-support/runtime-benchmarks",
+	"frame-system/runtime-benchmarks"
+] # feature: allow
+"#;
+
+		let mut fixer = AutoFixer::from_raw(before).unwrap();
+		fixer.add_to_feature("std", "frame-system/std").unwrap();
+		fixer
+			.add_to_feature("runtime-benchmarks", "frame-system/runtime-benchmarks")
+			.unwrap();
+		assert_eq!(fixer.to_string(), after);
+	}
 }
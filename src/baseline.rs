@@ -0,0 +1,196 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// SPDX-FileCopyrightText: Oliver Tale-Yazdi <oliver@tasty.limo>
+
+//! Record known lint findings, so that only new ones fail a run.
+//!
+//! A baseline is a JSON file with one entry per (crate, feature, dependency) finding. It is
+//! written with `--write-baseline` and read back with `--baseline`.
+
+use crate::cmd::lint::{Finding, FindingKind};
+use std::{collections::BTreeSet, path::Path};
+
+/// Version of the baseline file format.
+const VERSION: u32 = 1;
+
+/// Findings that are already known.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Baseline {
+	/// Version of the file format.
+	pub version: u32,
+	/// The known findings.
+	pub findings: BTreeSet<Entry>,
+}
+
+/// A single known finding.
+///
+/// Crates are referenced by name, since their ids contain local paths.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+	/// Name of the crate that has the problem.
+	#[serde(rename = "crate")]
+	pub krate: String,
+	/// The feature that has the problem.
+	pub feature: String,
+	/// What kind of problem it is.
+	pub kind: FindingKind,
+	/// The dependency that causes the problem, if any.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub dependency: Option<String>,
+}
+
+impl Baseline {
+	/// Create a baseline that contains all unfixed `findings`.
+	pub fn from_findings(findings: &[Finding]) -> Self {
+		let findings = findings.iter().filter(|f| !f.fixed).flat_map(Entry::of).collect();
+		Self { version: VERSION, findings }
+	}
+
+	/// Read a baseline from a JSON file.
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let raw = std::fs::read_to_string(path)
+			.map_err(|e| format!("Failed to read baseline {}: {e}", path.display()))?;
+		let baseline: Self = serde_json::from_str(&raw)
+			.map_err(|e| format!("Failed to parse baseline {}: {e}", path.display()))?;
+
+		if baseline.version != VERSION {
+			return Err(format!(
+				"Unsupported baseline version {} in {}, expected {VERSION}",
+				baseline.version,
+				path.display()
+			))
+		}
+		Ok(baseline)
+	}
+
+	/// Write the baseline to a JSON file.
+	pub fn save(&self, path: &Path) -> Result<(), String> {
+		let raw = serde_json::to_string_pretty(self)
+			.map_err(|e| format!("Failed to serialize baseline: {e}"))?;
+		std::fs::write(path, raw + "\n")
+			.map_err(|e| format!("Failed to write baseline {}: {e}", path.display()))
+	}
+
+	/// Split the dependencies of all `findings` into known and new ones.
+	///
+	/// Known dependencies are moved into a separate finding that is marked as `known`.
+	pub fn classify(&self, findings: Vec<Finding>) -> Vec<Finding> {
+		let mut classified = Vec::new();
+
+		for finding in findings {
+			if finding.dependencies.is_empty() {
+				let known = Entry::of(&finding).iter().all(|e| self.contains(e));
+				classified.push(Finding { known, ..finding });
+				continue
+			}
+			let (known, new): (Vec<_>, Vec<_>) =
+				finding.dependencies.iter().cloned().partition(|dep| {
+					self.contains(&Entry {
+						krate: finding.name.clone(),
+						feature: finding.feature.clone(),
+						kind: finding.kind,
+						dependency: Some(dep.clone()),
+					})
+				});
+
+			if !new.is_empty() {
+				classified.push(Finding { dependencies: new, ..finding.clone() });
+			}
+			if !known.is_empty() {
+				classified.push(Finding { dependencies: known, known: true, ..finding });
+			}
+		}
+
+		classified
+	}
+
+	/// Whether `entry` is a known finding.
+	pub fn contains(&self, entry: &Entry) -> bool {
+		self.findings.contains(entry)
+	}
+}
+
+impl Entry {
+	/// The entries of a finding; one per dependency.
+	pub fn of(finding: &Finding) -> Vec<Self> {
+		let entry = |dependency| Self {
+			krate: finding.name.clone(),
+			feature: finding.feature.clone(),
+			kind: finding.kind,
+			dependency,
+		};
+
+		if finding.dependencies.is_empty() {
+			vec![entry(None)]
+		} else {
+			finding.dependencies.iter().map(|d| entry(Some(d.clone()))).collect()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn finding(deps: &[&str], fixed: bool) -> Finding {
+		Finding {
+			krate: "frame-support 1.0.0 (path+file:///frame/support)".into(),
+			name: "frame-support".into(),
+			manifest_path: "/frame/support/Cargo.toml".into(),
+			feature: "std".into(),
+			kind: FindingKind::MissingPropagation,
+			dependencies: deps.iter().map(|d| d.to_string()).collect(),
+			fixed,
+			known: false,
+		}
+	}
+
+	#[test]
+	fn from_findings_works() {
+		let baseline = Baseline::from_findings(&[
+			finding(&["sp-io", "sp-core"], false),
+			finding(&["sp-std"], true),
+		]);
+		let entry = |dep: &str| Entry {
+			krate: "frame-support".into(),
+			feature: "std".into(),
+			kind: FindingKind::MissingPropagation,
+			dependency: Some(dep.into()),
+		};
+
+		assert_eq!(baseline.findings.len(), 2);
+		assert!(baseline.contains(&entry("sp-io")));
+		assert!(baseline.contains(&entry("sp-core")));
+		assert!(!baseline.contains(&entry("sp-std")), "Fixed findings are not recorded");
+	}
+
+	#[test]
+	fn classify_works() {
+		let baseline = Baseline::from_findings(&[finding(&["sp-io"], false)]);
+		let classified = baseline.classify(vec![finding(&["sp-io", "sp-core"], false)]);
+
+		assert_eq!(classified.len(), 2);
+		assert_eq!(classified[0].dependencies, vec!["sp-core"]);
+		assert!(!classified[0].known);
+		assert_eq!(classified[1].dependencies, vec!["sp-io"]);
+		assert!(classified[1].known);
+	}
+
+	#[test]
+	fn save_load_roundtrip_works() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("baseline.json");
+		let baseline = Baseline::from_findings(&[finding(&["sp-io"], false)]);
+
+		baseline.save(&path).unwrap();
+		assert_eq!(Baseline::load(&path).unwrap(), baseline);
+	}
+
+	#[test]
+	fn unknown_version_errors() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("baseline.json");
+		std::fs::write(&path, r#"{ "version": 2, "findings": [] }"#).unwrap();
+
+		assert!(Baseline::load(&path).unwrap_err().contains("Unsupported baseline version 2"));
+	}
+}
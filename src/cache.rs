@@ -0,0 +1,276 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// SPDX-FileCopyrightText: Oliver Tale-Yazdi <oliver@tasty.limo>
+
+//! Cache the output of `cargo metadata` between invocations.
+//!
+//! Each entry remembers a fingerprint of the files that it was derived from: the root manifest,
+//! the lockfile and the manifests of all local crates. Glob patterns in the `members` and
+//! `exclude` of the workspace are expanded as well, so that new crates are noticed. An entry is
+//! only used as long as none of this changed.
+
+use cargo_metadata::Metadata;
+use regex::Regex;
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	path::{Path, PathBuf},
+};
+
+/// Name of the cache directory within the target directory.
+pub const CACHE_DIR: &str = "feature-cache";
+
+/// An on-disk cache of cargo metadata.
+pub struct Cache {
+	dir: PathBuf,
+}
+
+/// A cached metadata together with the fingerprints of its inputs.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Entry {
+	/// Path -> Length and hash of its content, or `None` if it did not exist.
+	inputs: BTreeMap<PathBuf, Option<(usize, u64)>>,
+	/// Glob pattern of the workspace -> Crate directories that it matched.
+	globs: BTreeMap<String, BTreeSet<PathBuf>>,
+	metadata: Metadata,
+}
+
+impl Cache {
+	/// Use the cache in `dir`.
+	pub fn new(dir: PathBuf) -> Self {
+		Self { dir }
+	}
+
+	/// The cache in the target directory of the workspace that contains `manifest`.
+	///
+	/// Respects `CARGO_TARGET_DIR` and otherwise uses the `target` folder next to the outermost
+	/// manifest that declares a `[workspace]`.
+	pub fn for_manifest(manifest: &Path) -> Self {
+		if let Ok(target) = std::env::var("CARGO_TARGET_DIR") {
+			return Self::new(Path::new(&target).join(CACHE_DIR))
+		}
+		let dir = manifest.parent().unwrap_or(Path::new("."));
+		let root = dir
+			.ancestors()
+			.filter(|dir| {
+				std::fs::read_to_string(dir.join("Cargo.toml"))
+					.ok()
+					.and_then(|raw| raw.parse::<toml_edit::Document>().ok())
+					.is_some_and(|doc| doc.contains_key("workspace"))
+			})
+			.last()
+			.unwrap_or(dir);
+
+		Self::new(root.join("target").join(CACHE_DIR))
+	}
+
+	/// The cached metadata for `key`, if its inputs did not change.
+	pub fn load(&self, key: &str) -> Option<Metadata> {
+		let raw = std::fs::read_to_string(self.path(key)).ok()?;
+		let entry: Entry = serde_json::from_str(&raw).ok()?;
+
+		let fresh = entry.inputs.iter().all(|(path, hash)| &fingerprint(path) == hash) &&
+			globs(entry.metadata.workspace_root.as_std_path()) == entry.globs;
+		fresh.then_some(entry.metadata)
+	}
+
+	/// Store `metadata` under `key`.
+	pub fn store(&self, key: &str, metadata: &Metadata) -> Result<(), String> {
+		let inputs = inputs(metadata).into_iter().map(|path| {
+			let hash = fingerprint(&path);
+			(path, hash)
+		});
+		let globs = globs(metadata.workspace_root.as_std_path());
+		let entry = Entry { inputs: inputs.collect(), globs, metadata: metadata.clone() };
+		let raw = serde_json::to_string(&entry)
+			.map_err(|e| format!("Failed to serialize metadata: {e}"))?;
+
+		std::fs::create_dir_all(&self.dir)
+			.map_err(|e| format!("Failed to create cache dir {}: {e}", self.dir.display()))?;
+		std::fs::write(self.path(key), raw)
+			.map_err(|e| format!("Failed to write cache {}: {e}", self.path(key).display()))
+	}
+
+	fn path(&self, key: &str) -> PathBuf {
+		self.dir.join(format!("metadata-{:016x}.json", hash(key.as_bytes())))
+	}
+}
+
+/// The files that the metadata of a workspace is derived from.
+fn inputs(meta: &Metadata) -> Vec<PathBuf> {
+	let root = meta.workspace_root.as_std_path();
+	let mut inputs = vec![root.join("Cargo.toml"), root.join("Cargo.lock")];
+	// Local crates have no source.
+	let local = meta.packages.iter().filter(|pkg| pkg.source.is_none());
+	inputs.extend(local.map(|pkg| pkg.manifest_path.clone().into_std_path_buf()));
+
+	inputs
+}
+
+/// Length and hash of the content of `path` or `None` if it cannot be read.
+fn fingerprint(path: &Path) -> Option<(usize, u64)> {
+	std::fs::read(path).ok().map(|content| (content.len(), hash(&content)))
+}
+
+/// FNV-1a hash of `data`.
+///
+/// The hashers of std are not guaranteed to be stable across releases, which would make the
+/// cache entries of another toolchain look stale or, worse, fresh.
+fn hash(data: &[u8]) -> u64 {
+	data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+		(hash ^ *byte as u64).wrapping_mul(0x100_0000_01b3)
+	})
+}
+
+/// The crate directories that the glob patterns in the `members` and `exclude` of the workspace
+/// manifest in `root` match.
+fn globs(root: &Path) -> BTreeMap<String, BTreeSet<PathBuf>> {
+	let Some(doc) = std::fs::read_to_string(root.join("Cargo.toml"))
+		.ok()
+		.and_then(|raw| raw.parse::<toml_edit::Document>().ok())
+	else {
+		return BTreeMap::new()
+	};
+	let patterns = ["members", "exclude"]
+		.into_iter()
+		.filter_map(|key| doc.get("workspace")?.get(key)?.as_array())
+		.flatten()
+		.filter_map(|pattern| pattern.as_str())
+		.filter(|pattern| pattern.contains(['*', '?', '[']));
+
+	patterns.map(|pattern| (pattern.to_string(), expand(root, pattern))).collect()
+}
+
+/// All directories below `root` that match the glob `pattern` and contain a manifest.
+fn expand(root: &Path, pattern: &str) -> BTreeSet<PathBuf> {
+	let mut found = vec![root.to_path_buf()];
+
+	for component in pattern.split('/') {
+		if !component.contains(['*', '?', '[']) {
+			found.iter_mut().for_each(|dir| dir.push(component));
+			continue
+		}
+		let Some(regex) = glob_regex(component) else { return BTreeSet::new() };
+		found = found
+			.iter()
+			.filter_map(|dir| std::fs::read_dir(dir).ok())
+			.flatten()
+			.filter_map(Result::ok)
+			.filter(|entry| entry.file_name().to_str().is_some_and(|name| regex.is_match(name)))
+			.map(|entry| entry.path())
+			.filter(|path| path.is_dir())
+			.collect();
+	}
+
+	found.into_iter().filter(|dir| dir.join("Cargo.toml").is_file()).collect()
+}
+
+/// Regex that matches the same file names as the glob `component`.
+fn glob_regex(component: &str) -> Option<Regex> {
+	let mut regex = String::from("^");
+	let mut class = false;
+
+	for c in component.chars() {
+		match c {
+			'*' if !class => regex.push_str(".*"),
+			'?' if !class => regex.push('.'),
+			'[' if !class => {
+				class = true;
+				regex.push('[');
+			},
+			'!' if class && regex.ends_with('[') => regex.push('^'),
+			'-' if class => regex.push('-'),
+			']' if class => {
+				class = false;
+				regex.push(']');
+			},
+			c => regex.push_str(&regex::escape(&c.to_string())),
+		}
+	}
+	regex.push('$');
+
+	Regex::new(&regex).ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Metadata of the `renamed` fixture that lives in `dir`.
+	fn metadata(dir: &Path) -> Metadata {
+		let raw = include_str!("../tests/fixtures/renamed.json");
+		let raw = raw.replace("/fixtures/renamed", dir.to_str().unwrap());
+		for manifest in ["", "root/", "dep-a/", "dep-b/", "dep-c/"] {
+			let manifest = dir.join(manifest).join("Cargo.toml");
+			std::fs::create_dir_all(manifest.parent().unwrap()).unwrap();
+			std::fs::write(manifest, "[package]").unwrap();
+		}
+		serde_json::from_str(&raw).unwrap()
+	}
+
+	#[test]
+	fn cache_works() {
+		let dir = tempfile::tempdir().unwrap();
+		let meta = metadata(dir.path());
+		let cache = Cache::new(dir.path().join("target").join(CACHE_DIR));
+
+		assert!(cache.load("key").is_none());
+		cache.store("key", &meta).unwrap();
+		assert_eq!(cache.load("key").unwrap().packages.len(), meta.packages.len());
+		assert!(cache.load("other").is_none(), "Keys are distinct");
+
+		std::fs::write(dir.path().join("Cargo.lock"), "").unwrap();
+		assert!(cache.load("key").is_none(), "Creating the lockfile invalidates");
+		cache.store("key", &meta).unwrap();
+		assert!(cache.load("key").is_some());
+
+		std::fs::write(dir.path().join("dep-b/Cargo.toml"), "[package]\nname = \"dep-b\"").unwrap();
+		assert!(cache.load("key").is_none(), "Changing a crate manifest invalidates");
+	}
+
+	#[test]
+	fn cache_notices_new_glob_members() {
+		let dir = tempfile::tempdir().unwrap();
+		let meta = metadata(dir.path());
+		let cache = Cache::new(dir.path().join("target").join(CACHE_DIR));
+		let manifest = "[workspace]\nmembers = [\"root\", \"dep-*\"]\nexclude = [\"dep-[!a-b]\"]";
+		std::fs::write(dir.path().join("Cargo.toml"), manifest).unwrap();
+		cache.store("key", &meta).unwrap();
+
+		std::fs::create_dir_all(dir.path().join("dep-d/src")).unwrap();
+		assert!(cache.load("key").is_some(), "Directories without manifest are no crates");
+
+		std::fs::write(dir.path().join("dep-d/Cargo.toml"), "[package]").unwrap();
+		assert!(cache.load("key").is_none(), "A new member invalidates");
+	}
+
+	#[rstest::rstest]
+	#[case("dep-*", "dep-a", true)]
+	#[case("dep-*", "root", false)]
+	#[case("dep-?", "dep-ab", false)]
+	#[case("dep-[a-b]", "dep-b", true)]
+	#[case("dep-[!a-b]", "dep-b", false)]
+	#[case("dep-[!a-b]", "dep-c", true)]
+	#[case("d.p", "dep", false)]
+	fn glob_regex_works(#[case] glob: &str, #[case] name: &str, #[case] matches: bool) {
+		assert_eq!(glob_regex(glob).unwrap().is_match(name), matches);
+	}
+
+	#[test]
+	fn hash_is_stable() {
+		assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+		assert_eq!(hash(b"feature"), 0x1e5c_9130_a426_fa9d);
+	}
+
+	#[test]
+	fn for_manifest_uses_workspace_root() {
+		// An explicit target dir of the environment takes precedence.
+		if std::env::var("CARGO_TARGET_DIR").is_ok() {
+			return
+		}
+		let dir = tempfile::tempdir().unwrap();
+		metadata(dir.path());
+		std::fs::write(dir.path().join("Cargo.toml"), "[workspace]").unwrap();
+
+		let cache = Cache::for_manifest(&dir.path().join("root/Cargo.toml"));
+		assert_eq!(cache.dir, dir.path().join("target").join(CACHE_DIR));
+	}
+}
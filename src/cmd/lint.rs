@@ -3,8 +3,20 @@
 
 //! Lint your feature usage by analyzing crate metadata.
 
-use crate::{autofix::AutoFixer, cmd::resolve_dep, CrateId};
-use cargo_metadata::PackageId;
+use super::Status;
+use crate::{
+	autofix::AutoFixer,
+	baseline::Baseline,
+	cmd::{build_dag, dependency_key, resolve_dep, DepKind},
+	config::{Config, PackageConfig},
+	rules::{
+		eval::{Binding, Evaluator},
+		Fix,
+	},
+	CrateId,
+};
+use cargo_metadata::{Dependency, Metadata, Package, PackageId};
+use regex::Regex;
 use std::{
 	collections::{BTreeMap, BTreeSet},
 	fs::canonicalize,
@@ -22,6 +34,10 @@ pub struct LintCmd {
 pub enum SubCommand {
 	/// Check whether features are properly propagated.
 	PropagateFeature(PropagateFeatureCmd),
+	/// Check the rules of a spec file.
+	Rules(RulesCmd),
+	/// Check that crates do not depend on forbidden crates.
+	ForbidDependency(ForbidDependencyCmd),
 }
 
 /// Verifies that rust features are properly propagated.
@@ -31,9 +47,19 @@ pub struct PropagateFeatureCmd {
 	#[clap(flatten)]
 	tree_args: super::TreeArgs,
 
-	/// The feature to check.
-	#[clap(long, required = true)]
-	feature: String,
+	/// The features to check.
+	///
+	/// Can be repeated or comma separated, eg. `--feature std,runtime-benchmarks`. Defaults to the
+	/// features of the workspace config.
+	#[clap(long = "feature", value_delimiter = ',')]
+	features: Vec<String>,
+
+	/// Path of the config file.
+	///
+	/// Defaults to the `feature.toml` in the workspace root or the
+	/// `[workspace.metadata.feature]` table of its manifest.
+	#[clap(long)]
+	config: Option<std::path::PathBuf>,
 
 	/// The packages to check. If empty, all packages are checked.
 	#[clap(long, short, num_args(0..))]
@@ -54,179 +80,998 @@ pub struct PropagateFeatureCmd {
 	/// Fix only issues with this package as feature source.
 	#[clap(long)]
 	fix_package: Option<String>,
+
+	/// Output format of the findings.
+	#[clap(long, value_enum, default_value_t = OutputFormat::Human)]
+	format: OutputFormat,
+
+	/// Treat these kinds of findings as errors.
+	///
+	/// Errors always result in a non-zero exit code, warnings only when they are denied.
+	#[clap(long, value_enum)]
+	deny: Vec<Deny>,
+
+	/// Report the findings of this baseline file as known, so that they do not fail the run.
+	#[clap(long)]
+	baseline: Option<std::path::PathBuf>,
+
+	/// Record all unfixed findings in this baseline file.
+	#[clap(long, conflicts_with = "baseline")]
+	write_baseline: Option<std::path::PathBuf>,
+
+	/// Only check crates whose manifest changed since this git revision and their dependants.
+	///
+	/// Reads the local git repository of the workspace.
+	#[clap(long)]
+	changed_since: Option<String>,
+}
+
+/// Checks the workspace-specific rules of a spec file.
+#[derive(Debug, clap::Parser)]
+pub struct RulesCmd {
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	tree_args: super::TreeArgs,
+
+	/// Path of the spec file that contains the rules.
+	#[clap(long)]
+	spec: std::path::PathBuf,
+
+	/// Apply the `auto-fix` actions of violated rules.
+	#[clap(long)]
+	fix: bool,
+}
+
+/// Verifies that crates do not depend on other crates that they should not depend on.
+#[derive(Debug, clap::Parser)]
+pub struct ForbidDependencyCmd {
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	tree_args: super::TreeArgs,
+
+	/// Regex for the names of the dependant crates.
+	#[clap(long)]
+	from: String,
+
+	/// Regex for the names of the forbidden dependencies.
+	#[clap(long)]
+	to: String,
+
+	/// Also forbid indirect dependencies.
+	///
+	/// The dev-dependencies of dependencies are not followed, since they are never built.
+	#[clap(long)]
+	transitive: bool,
+}
+
+/// Kind of findings that can be denied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Deny {
+	/// Fail on warnings as well.
+	Warnings,
+}
+
+/// Output format of the lint findings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+	/// Human readable text.
+	Human,
+	/// A JSON array with one record per finding.
+	Json,
+	/// A SARIF log for code-scanning tools.
+	Sarif,
+}
+
+/// A problem that was found by the lint.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Finding {
+	/// Id of the crate that has the problem.
+	#[serde(rename = "crate")]
+	pub krate: CrateId,
+	/// Name of the crate that has the problem.
+	pub name: String,
+	/// Path to the manifest of the crate.
+	pub manifest_path: String,
+	/// The feature that has the problem.
+	pub feature: String,
+	/// What kind of problem it is.
+	pub kind: FindingKind,
+	/// Names of the dependencies that cause the problem.
+	pub dependencies: Vec<String>,
+	/// Whether the problem was fixed by `--fix`.
+	pub fixed: bool,
+	/// Whether the problem is recorded in the baseline.
+	pub known: bool,
+}
+
+/// The kind of a [Finding].
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum FindingKind {
+	/// The crate does not have the feature, but some of its dependencies do.
+	MissingFeature,
+	/// The crate does not propagate the feature to some of its dependencies.
+	MissingPropagation,
+	/// The crate has the feature, but does not use it.
+	Unused,
+	/// The crate does not propagate the feature, but the default features of the dependency
+	/// enable it anyway.
+	ImplicitDefault,
+}
+
+impl FindingKind {
+	/// Whether this kind of finding is an error or just a warning.
+	pub fn is_error(&self) -> bool {
+		!matches!(self, Self::Unused | Self::ImplicitDefault)
+	}
+
+	/// Stable identifier of the lint that produces this kind of finding.
+	pub fn rule_id(&self) -> &'static str {
+		match self {
+			Self::MissingFeature => "feature_missing",
+			Self::MissingPropagation => "propagate_missing",
+			Self::Unused => "feature_maybe_unused",
+			Self::ImplicitDefault => "implicit_default",
+		}
+	}
+
+	/// Short description of the lint that produces this kind of finding.
+	pub fn description(&self) -> &'static str {
+		match self {
+			Self::MissingFeature => "A crate is missing a feature that its dependencies have.",
+			Self::MissingPropagation => "A crate does not propagate a feature to its dependencies.",
+			Self::Unused => "A crate has a feature that nothing uses.",
+			Self::ImplicitDefault =>
+				"A crate relies on the default features of a dependency to enable a feature.",
+		}
+	}
+}
+
+impl Finding {
+	/// Flatten the findings of all features into a list that is sorted by crate and feature.
+	///
+	/// Findings that do not show up in `remaining` anymore were fixed.
+	fn collect(
+		all: &[(String, Findings)],
+		remaining: &[(String, Findings)],
+		meta: &Metadata,
+	) -> Vec<Self> {
+		let lookup = |id: &str| {
+			meta.packages
+				.iter()
+				.find(|pkg| pkg.id.repr == id)
+				.unwrap_or_else(|| panic!("Could not find crate {id} in the metadata"))
+		};
+		let faulty_crates: BTreeSet<CrateId> =
+			all.iter().flat_map(|(_, f)| f.crates()).cloned().collect();
+		let mut findings = Vec::new();
+
+		for krate in faulty_crates {
+			let pkg = lookup(&krate);
+
+			for ((feature, found), (_, remaining)) in all.iter().zip(remaining.iter()) {
+				let kinds = [
+					(
+						FindingKind::MissingFeature,
+						&found.feature_missing,
+						&remaining.feature_missing,
+					),
+					(
+						FindingKind::MissingPropagation,
+						&found.propagate_missing,
+						&remaining.propagate_missing,
+					),
+					(
+						FindingKind::ImplicitDefault,
+						&found.implicit_default,
+						&remaining.implicit_default,
+					),
+				];
+
+				for (kind, found, remaining) in kinds {
+					let Some(deps) = found.get(&krate) else { continue };
+
+					findings.push(Finding {
+						krate: krate.clone(),
+						name: pkg.name.clone(),
+						manifest_path: pkg.manifest_path.to_string(),
+						feature: feature.clone(),
+						kind,
+						dependencies: deps.iter().map(|d| d.name.clone()).collect(),
+						fixed: !remaining.contains_key(&krate),
+						known: false,
+					});
+				}
+				if found.feature_maybe_unused.contains(&krate) {
+					findings.push(Finding {
+						krate: krate.clone(),
+						name: pkg.name.clone(),
+						manifest_path: pkg.manifest_path.to_string(),
+						feature: feature.clone(),
+						kind: FindingKind::Unused,
+						dependencies: Vec::new(),
+						fixed: !remaining.feature_maybe_unused.contains(&krate),
+						known: false,
+					});
+				}
+			}
+		}
+
+		findings
+	}
+}
+
+/// Print the findings grouped by crate and feature.
+fn print_human(findings: &[Finding]) {
+	let (mut krate, mut feature) = (None, None);
+
+	for finding in findings {
+		if krate != Some(&finding.krate) {
+			println!("crate {:?}", finding.name);
+			krate = Some(&finding.krate);
+			feature = None;
+		}
+		if feature != Some(&finding.feature) {
+			println!("  feature {:?}", finding.feature);
+			feature = Some(&finding.feature);
+		}
+
+		let joined = finding.dependencies.join("\n      ");
+		let known = if finding.known { " (known)" } else { "" };
+		match finding.kind {
+			FindingKind::MissingFeature => println!(
+				"    must exist because {} dependencies have it{known}:\n      {}",
+				finding.dependencies.len(),
+				joined
+			),
+			FindingKind::MissingPropagation =>
+				println!("    must propagate to{known}:\n      {joined}"),
+			FindingKind::Unused => println!("    is not used{known}"),
+			FindingKind::ImplicitDefault =>
+				println!("    is implicitly enabled via default{known}:\n      {joined}"),
+		}
+	}
+}
+
+/// A dependency of a crate as it is referenced from within the crates features.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct FeatureDep {
+	/// Id of the resolved dependency.
+	id: CrateId,
+	/// Name of the dependency in the manifest of the crate; the new name if it is renamed.
+	name: String,
+	/// Whether the dependency is optional.
+	///
+	/// Optional dependencies use the weak `dep?/feature` syntax to not implicitly enable them.
+	optional: bool,
+}
+
+impl FeatureDep {
+	/// The entry that propagates `feature` to this dependency.
+	fn propagation(&self, feature: &str) -> String {
+		if self.optional {
+			format!("{}?/{}", self.name, feature)
+		} else {
+			format!("{}/{}", self.name, feature)
+		}
+	}
+
+	/// Whether any of the `enabled` entries propagates `feature` to this dependency.
+	///
+	/// Optional dependencies can either use the weak or strong syntax, since the strong one
+	/// correctly propagates the feature as well - it just also enables the dependency.
+	fn is_propagated(&self, feature: &str, enabled: &[String]) -> bool {
+		let strong = format!("{}/{}", self.name, feature);
+		let weak = format!("{}?/{}", self.name, feature);
+
+		enabled.contains(&strong) || (self.optional && enabled.contains(&weak))
+	}
 }
 
 impl LintCmd {
-	pub(crate) fn run(&self) {
+	pub(crate) fn run(&self) -> Result<Status, String> {
 		match &self.subcommand {
 			SubCommand::PropagateFeature(cmd) => cmd.run(),
+			SubCommand::Rules(cmd) => cmd.run(),
+			SubCommand::ForbidDependency(cmd) => cmd.run(),
 		}
 	}
 }
 
 impl PropagateFeatureCmd {
-	pub fn run(&self) {
+	/// Check the features and return whether there are any unfixed findings.
+	pub fn run(&self) -> Result<Status, String> {
 		log::info!("Using manifest: {:?}", self.tree_args.manifest_path);
+		// `--features` only selects the features for resolving the dependencies.
+		if self.features.is_empty() && !self.tree_args.crate_features.is_empty() {
+			return Err(format!(
+				"--features only selects the crate features of the metadata: Pass the features to check with --feature {}",
+				self.tree_args.crate_features.join(",")
+			))
+		}
+		let mut meta = self.tree_args.load_metadata()?;
 		// Allowed dir that we can write to.
-		let allowed_dir = canonicalize(&self.tree_args.manifest_path).unwrap();
-		let allowed_dir = allowed_dir.parent().unwrap();
-		let feature = self.feature.clone();
-		let meta = self.tree_args.load_metadata().expect("Loads metadata");
-		let pkgs = meta.packages.iter().collect::<Vec<_>>();
-		let mut to_check = pkgs.clone();
-		if !self.packages.is_empty() {
-			to_check =
-				pkgs.iter().filter(|pkg| self.packages.contains(&pkg.name)).cloned().collect();
+		let allowed_dir = self.tree_args.allowed_dir(&meta)?;
+		let config = match &self.config {
+			Some(path) => Config::from_file(path)?,
+			None => Config::load(&meta)?,
+		};
+		let features = if self.features.is_empty() { &config.features } else { &self.features };
+		if features.is_empty() {
+			return Err(
+				"No features to check: Pass them with --feature or add them to the config".into()
+			)
+		}
+		let mut to_check = meta
+			.packages
+			.iter()
+			.filter(|pkg| self.packages.is_empty() || self.packages.contains(&pkg.name))
+			.filter(|pkg| !config.exclude.contains(&pkg.name))
+			.map(|pkg| pkg.id.clone())
+			.collect::<Vec<_>>();
+		if let Some(rev) = &self.changed_since {
+			let changed = Self::changed_since(rev, &meta)?;
+			to_check.retain(|id| changed.contains(id));
+			if to_check.is_empty() {
+				if self.format == OutputFormat::Human {
+					println!("No crates changed since {rev}");
+				} else {
+					log::info!("No crates changed since {rev}");
+					self.print_serialized(&[], &meta)?;
+				}
+				return Ok(Status::Clean)
+			}
+			log::info!("Checking {} crates that changed since {rev}", to_check.len());
 		}
 		if to_check.is_empty() {
-			panic!("No packages found: {:?}", self.packages);
+			return Err(format!("No packages found: {:?}", self.packages))
+		}
+
+		if self.format == OutputFormat::Human {
+			if let Some(root) = meta.root_package() {
+				println!("Analyzing {root:?}");
+			} else {
+				println!("Analyzing workspace");
+			}
+			println!("Using {}", self.tree_args.feature_selection());
 		}
 
-		if let Some(root) = meta.root_package() {
-			println!("Analyzing {root:?}");
+		// All features share the same metadata.
+		let mut all = features
+			.iter()
+			.map(|feature| (feature.clone(), Findings::default()))
+			.collect::<Vec<_>>();
+		let mut fixes = 0;
+		// Package configs are only loaded for crates with findings.
+		let mut allows = BTreeMap::<CrateId, PackageConfig>::new();
+		let mut suppressed = BTreeSet::new();
+		// Fixing a crate can reveal new problems in its dependants, so we repeat until nothing
+		// changes anymore. The last check contains the problems that could not be fixed.
+		let remaining = loop {
+			let mut found = Vec::new();
+			for feature in features.iter() {
+				let kinds = config.dep_kinds(feature);
+				let mut findings = Findings::check(feature, &to_check, kinds, &meta);
+				if config.is_enabled(FindingKind::Unused) {
+					findings.check_unused(feature, &to_check, &meta);
+				}
+				findings.retain_enabled(&config);
+				for krate in findings.crates() {
+					if !allows.contains_key(krate) {
+						let pkg = meta.packages.iter().find(|pkg| &pkg.id.repr == krate);
+						let pkg = pkg.expect("Crates with findings are in the metadata");
+						allows.insert(krate.clone(), PackageConfig::load(pkg)?);
+					}
+				}
+				suppressed.extend(findings.suppress(feature, &allows));
+				found.push((feature.clone(), findings));
+			}
+			let fixed = if self.fix { self.fix(&found, &mut meta, &allowed_dir)? } else { 0 };
+
+			for ((_, all), (_, found)) in all.iter_mut().zip(found.iter()) {
+				all.extend(found.clone());
+			}
+			fixes += fixed;
+			if fixed == 0 {
+				break found
+			}
+		};
+
+		let mut findings = Finding::collect(&all, &remaining, &meta);
+		let baseline = match (&self.write_baseline, &self.baseline) {
+			(Some(path), _) => {
+				let baseline = Baseline::from_findings(&findings);
+				baseline.save(path)?;
+				log::info!(
+					"Wrote {} findings to baseline {}",
+					baseline.findings.len(),
+					path.display()
+				);
+				Some(baseline)
+			},
+			(None, Some(path)) => Some(Baseline::load(path)?),
+			(None, None) => None,
+		};
+		if let Some(baseline) = baseline {
+			let current = Baseline::from_findings(&findings);
+			let stale = baseline.findings.difference(&current.findings).count();
+			if stale > 0 {
+				log::info!("{stale} findings of the baseline do not occur anymore");
+			}
+			findings = baseline.classify(findings);
+		}
+		let errors = findings.iter().filter(|f| f.kind.is_error()).count();
+		let warnings = findings.len() - errors;
+		let known = findings.iter().filter(|f| f.known).count();
+		match self.format {
+			OutputFormat::Human => {
+				print_human(&findings);
+				if errors > 0 || warnings > 0 || !suppressed.is_empty() {
+					print!("Generated {errors} errors and {warnings} warnings and fixed {fixes} issues.");
+					if known > 0 {
+						print!(" {known} of them are known from the baseline.");
+					}
+					if !suppressed.is_empty() {
+						print!(" Suppressed {} findings.", suppressed.len());
+					}
+					println!();
+				}
+			},
+			OutputFormat::Json | OutputFormat::Sarif => self.print_serialized(&findings, &meta)?,
+		}
+
+		// Known findings never fail the run.
+		let mut new = findings.iter().filter(|f| !f.known);
+		let unfixed = new.clone().any(|f| f.kind.is_error() && !f.fixed);
+		let warned = new.any(|f| !f.kind.is_error());
+		if unfixed || (self.deny.contains(&Deny::Warnings) && warned) {
+			Ok(Status::Findings)
 		} else {
-			println!("Analyzing workspace");
+			Ok(Status::Clean)
 		}
-		let lookup = |id: &str| {
-			let id = PackageId { repr: id.to_string() }; // TODO optimize
-			pkgs.iter()
-				.find(|pkg| pkg.id == id)
-				.unwrap_or_else(|| panic!("Could not find crate {id} in the metadata"))
+	}
+
+	/// Print the findings in the machine readable output format.
+	fn print_serialized(&self, findings: &[Finding], meta: &Metadata) -> Result<(), String> {
+		let json = match self.format {
+			OutputFormat::Human => unreachable!("Human output is not serialized"),
+			OutputFormat::Json => serde_json::to_string_pretty(&serde_json::json!({
+				"feature_selection": self.tree_args.feature_selection(),
+				"findings": findings,
+			})),
+			OutputFormat::Sarif => serde_json::to_string_pretty(&crate::sarif::to_sarif(
+				findings,
+				meta.workspace_root.as_std_path(),
+				&self.tree_args.feature_selection(),
+			)),
 		};
+		let json = json.map_err(|e| format!("Failed to serialize findings: {e}"))?;
+		println!("{json}");
 
-		// (Crate that is not forwarding the feature) -> (Dependency that it is not forwarded to)
-		let mut propagate_missing = BTreeMap::<CrateId, BTreeSet<CrateId>>::new();
-		// (Crate that missing the feature) -> (Dependency that has it)
-		let mut feature_missing = BTreeMap::<CrateId, BTreeSet<CrateId>>::new();
-		// Crate that has the feature but does not need it.
-		let mut feature_maybe_unused = BTreeSet::<CrateId>::new();
+		Ok(())
+	}
 
-		for pkg in to_check.iter() {
-			let mut feature_used = false;
-			// TODO that it does not enable other features.
+	/// The crates whose manifest changed since `rev` and their direct dependants.
+	///
+	/// All workspace members inherit from the workspace manifest, eg. its
+	/// `[workspace.dependencies]` or `[patch]` sections, so they all changed if it did.
+	fn changed_since(rev: &str, meta: &Metadata) -> Result<BTreeSet<PackageId>, String> {
+		let manifests = crate::git::changed_manifests(meta.workspace_root.as_std_path(), rev)?;
+		let root = meta.workspace_root.join("Cargo.toml");
+		let workspace_changed = manifests.iter().any(|m| m == root.as_std_path());
+		let changed = meta
+			.packages
+			.iter()
+			.filter(|pkg| {
+				(workspace_changed && meta.workspace_members.contains(&pkg.id)) ||
+					manifests.iter().any(|m| m == pkg.manifest_path.as_std_path())
+			})
+			.map(|pkg| pkg.id.clone())
+			.collect::<BTreeSet<_>>();
+		let dependants = meta.packages.iter().filter(|pkg| {
+			pkg.dependencies
+				.iter()
+				.filter_map(|dep| resolve_dep(pkg, dep, meta))
+				.any(|dep| changed.contains(&dep.id))
+		});
+		let dependants = dependants.map(|pkg| pkg.id.clone()).collect::<Vec<_>>();
 
-			for dep in pkg.dependencies.iter() {
-				// TODO handle default features.
-				// Resolve the dep according to the metadata.
-				let resolved = resolve_dep(pkg, dep, &meta);
+		Ok(changed.into_iter().chain(dependants).collect())
+	}
 
-				let Some(dep) = resolved else {
-					// Either outside workspace or not resolved, possibly due to not being used at all because of the target or whatever.
-					feature_used = true;
-					continue;
-				};
+	/// Fix the `findings` in all crates below `allowed_dir` and return the number of fixes.
+	///
+	/// The features in `meta` are updated accordingly, so that a subsequent check sees the fixes.
+	fn fix(
+		&self,
+		findings: &[(String, Findings)],
+		meta: &mut Metadata,
+		allowed_dir: &std::path::Path,
+	) -> Result<usize, String> {
+		let faulty_crates: BTreeSet<CrateId> = findings
+			.iter()
+			.flat_map(|(_, f)| f.propagate_missing.keys().chain(f.feature_missing.keys()))
+			.cloned()
+			.collect();
+		let mut fixes = 0;
 
-				if dep.features.contains_key(&feature) {
-					match pkg.features.get(&feature) {
-						None => {
-							feature_missing
-								.entry(pkg.id.to_string())
-								.or_default()
-								.insert(dep.id.to_string());
-						},
-						Some(enabled) => {
-							if !enabled.contains(&format!("{}/{}", dep.name, feature)) {
-								propagate_missing
-									.entry(pkg.id.to_string())
-									.or_default()
-									.insert(dep.id.to_string());
-							} else {
-								// All ok
-								feature_used = true;
-							}
-						},
+		for krate in faulty_crates {
+			let krate = meta
+				.packages
+				.iter_mut()
+				.find(|pkg| pkg.id.repr == krate)
+				.unwrap_or_else(|| panic!("Could not find crate {krate} in the metadata"));
+			if self.fix_package.as_ref().is_some_and(|p| p != &krate.name) {
+				continue
+			}
+			let Some(krate_path) = fixable_manifest(krate, allowed_dir)? else { continue };
+			// Fixes for all features are written at once.
+			let mut fixer = AutoFixer::from_manifest(&krate_path)?;
+			let mut krate_fixes = 0;
+
+			for (feature, findings) in findings.iter() {
+				let missing = findings.feature_missing.get(&krate.id.to_string());
+				let propagate = findings.propagate_missing.get(&krate.id.to_string());
+
+				for dep in missing.into_iter().chain(propagate).flatten() {
+					let dep_name = &dep.name;
+					if self.fix_dependency.as_ref().is_some_and(|d| d != dep_name) {
+						continue
 					}
+					let entry = dep.propagation(feature);
+					fixer.add_to_feature(feature, &entry)?;
+					krate.features.entry(feature.clone()).or_default().push(entry);
+					log::warn!("Added feature {feature} to {dep_name} in {}", krate.name);
+					krate_fixes += 1;
 				}
 			}
-
-			if !feature_used && pkg.features.contains_key(&feature) {
-				feature_maybe_unused.insert(pkg.id.to_string());
+			if krate_fixes > 0 {
+				fixer.save()?;
 			}
+			fixes += krate_fixes;
 		}
-		let faulty_crates: BTreeSet<CrateId> = propagate_missing
-			.keys()
-			.chain(feature_missing.keys())
-			.chain(feature_maybe_unused.iter())
-			.cloned()
-			.collect();
 
-		let (mut errors, warnings) = (0, 0);
-		let mut fixes = 0;
-		for krate in faulty_crates {
-			let krate = lookup(&krate);
-			// check if we can modify in allowed_dir
-			let krate_path = canonicalize(krate.manifest_path.clone().into_std_path_buf()).unwrap();
-			let mut fixer = if krate_path.starts_with(allowed_dir) {
-				Some(AutoFixer::from_manifest(&krate_path).unwrap())
-			} else {
-				log::info!(
-					"Cannot fix {} because it is not in the allowed directory {}",
-					krate.name,
-					allowed_dir.display()
-				);
-				None
-			};
-			println!("crate {:?}\n  feature {:?}", krate.name, feature);
+		Ok(fixes)
+	}
+}
 
-			// join
-			if let Some(deps) = feature_missing.get(&krate.id.to_string()) {
-				let joined = deps
-					.iter()
-					.map(|d| lookup(d))
-					.map(|dep| dep.name.to_string())
-					.collect::<Vec<_>>()
-					.join("\n      ");
-				println!(
-					"    must exit because {} dependencies have it:\n      {}",
-					deps.len(),
-					joined
-				);
-				errors += 1;
+impl RulesCmd {
+	/// Check all rules and return whether any of them is violated.
+	pub fn run(&self) -> Result<Status, String> {
+		let raw = std::fs::read_to_string(&self.spec)
+			.map_err(|e| format!("Failed to read spec file: {e}"))?;
+		let spec = crate::rules::parse::parse(&raw)?;
+		let meta = self.tree_args.load_metadata()?;
+		// Allowed dir that we can write to.
+		let allowed_dir = self.tree_args.allowed_dir(&meta)?;
+		println!("Using {}", self.tree_args.feature_selection());
+		let eval = Evaluator::new(&meta)?;
+		let (mut errors, mut fixes) = (0, 0);
+
+		for test in spec.tests.iter() {
+			let violations = eval.violations(test);
+			if violations.is_empty() {
+				continue
 			}
-			if let Some(deps) = propagate_missing.get(&krate.id.to_string()) {
-				let joined = deps
+			println!("test {:?}\n  error: {}", test.name, test.otherwise.error);
+
+			for binding in violations {
+				let joined = binding
 					.iter()
-					.map(|d| lookup(d))
-					.map(|dep| dep.name.to_string())
+					.map(|(var, id)| format!("{var} = {}", eval.krate(id).name))
 					.collect::<Vec<_>>()
-					.join("\n      ");
-				println!("    must propagate to:\n      {joined}");
-
-				if self.fix && self.fix_package.as_ref().map_or(true, |p| p == &krate.name) {
-					for dep in deps {
-						let dep_name = &lookup(dep).name;
-						if self.fix_dependency.as_ref().map_or(true, |d| d == dep_name) {
-							if let Some(fixer) = fixer.as_mut() {
-								fixer
-									.add_to_feature(
-										&feature,
-										format!("{dep_name}/{feature}").as_str(),
-									)
-									.unwrap();
-								log::warn!(
-									"Added feature {feature} to {dep_name} in {}",
-									krate.name
-								);
-								fixes += 1;
-							}
-						}
+					.join(", ");
+				println!("    {joined}");
+
+				if self.fix && !test.otherwise.fixes.is_empty() {
+					let mut fixed = true;
+					for fix in test.otherwise.fixes.iter() {
+						fixed &= Self::fix(fix, &binding, &eval, &allowed_dir)?;
+					}
+					if fixed {
+						fixes += 1;
+						continue
 					}
 				}
 				errors += 1;
 			}
-			if fixes > 0 {
-				fixer.unwrap().save().unwrap();
+		}
+		if errors > 0 || fixes > 0 {
+			println!("Generated {errors} errors and fixed {fixes} issues.");
+		}
+
+		Ok(if errors > 0 { Status::Findings } else { Status::Clean })
+	}
+
+	/// Apply an auto-fix to the bound crates and return whether it succeeded.
+	fn fix(
+		fix: &Fix,
+		binding: &Binding,
+		eval: &Evaluator,
+		allowed_dir: &std::path::Path,
+	) -> Result<bool, String> {
+		let krate = match fix {
+			Fix::EnableFeature { krate, .. } | Fix::RemoveDependency { krate, .. } =>
+				&binding[krate],
+		};
+		let pkg = eval.krate(krate);
+		let Some(krate_path) = fixable_manifest(pkg, allowed_dir)? else { return Ok(false) };
+		let mut fixer = AutoFixer::from_manifest(&krate_path)?;
+
+		match fix {
+			Fix::EnableFeature { feature, entry, .. } => {
+				// Crate variables in the entry refer to a dependency.
+				let entry = match entry.split_once('/') {
+					Some((dep, f)) => match binding.get(dep.trim_end_matches('?')) {
+						Some(id) => {
+							let name = eval.dependency_key(krate, id);
+							let optional = pkg
+								.dependencies
+								.iter()
+								.any(|d| d.optional && dependency_key(d) == name);
+							FeatureDep { id: id.clone(), name, optional }.propagation(f)
+						},
+						None => entry.clone(),
+					},
+					None => entry.clone(),
+				};
+				fixer.add_to_feature(feature, &entry)?;
+				log::warn!("Added {entry} to feature {feature} in {}", pkg.name);
+			},
+			Fix::RemoveDependency { dependency, .. } => {
+				let dep = eval.dependency_key(krate, &binding[dependency]);
+				if !fixer.remove_dependency(&dep)? {
+					log::warn!(
+						"Cannot remove {dep} from {} since it is no direct dependency",
+						pkg.name
+					);
+					return Ok(false)
+				}
+				log::warn!("Removed dependency {dep} from {}", pkg.name);
+			},
+		}
+		fixer.save()?;
+
+		Ok(true)
+	}
+}
+
+impl ForbidDependencyCmd {
+	/// Check the dependencies and return whether there are any forbidden ones.
+	pub fn run(&self) -> Result<Status, String> {
+		let from = Regex::new(&self.from).map_err(|e| format!("Invalid --from regex: {e}"))?;
+		let to = Regex::new(&self.to).map_err(|e| format!("Invalid --to regex: {e}"))?;
+		let meta = self.tree_args.load_metadata()?;
+		println!("Using {}", self.tree_args.feature_selection());
+		let (dag, index) = build_dag(&meta)?;
+		let mut violations = 0;
+
+		for (id, krate) in index.iter().filter(|(_, krate)| from.is_match(&krate.name)) {
+			// Dev-dependencies of dependencies are never built, so they are not followed.
+			let depth = if self.transitive { None } else { Some(1) };
+
+			for dep in dag.reachable(id, depth).into_keys() {
+				if dep == id || !to.is_match(&index[dep].name) {
+					continue
+				}
+				let witness = dag.any_path(id, dep).expect("Dependencies are reachable");
+				let mut names = Vec::new();
+				witness.for_each(|id| names.push(index[id].name.as_str()));
+
+				println!("crate {:?} must not depend on {:?}", krate.name, index[dep].name);
+				println!("  {}", names.join(" -> "));
+				violations += 1;
 			}
-			//if let Some(_dep) = feature_maybe_unused.get(&krate.id.to_string()) {
-			//	if !feature_missing.contains_key(&krate.id.to_string()) &&
-			// !propagate_missing.contains_key(&krate.id.to_string()) 	{
-			//		println!("    is not used by any dependencies");
-			//		warnings += 1;
-			//	}
-			//}
 		}
-		if errors > 0 || warnings > 0 {
-			println!("Generated {errors} errors and {warnings} warnings and fixed {fixes} issues.");
+		if violations > 0 {
+			println!("Found {violations} forbidden dependencies.");
+			Ok(Status::Findings)
+		} else {
+			Ok(Status::Clean)
+		}
+	}
+}
+
+/// The problems of a single feature.
+#[derive(Debug, Default, Clone)]
+struct Findings {
+	/// (Crate that is not forwarding the feature) -> (Dependency that it is not forwarded to)
+	propagate_missing: BTreeMap<CrateId, BTreeSet<FeatureDep>>,
+	/// (Crate that missing the feature) -> (Dependency that has it)
+	feature_missing: BTreeMap<CrateId, BTreeSet<FeatureDep>>,
+	/// Crate that has the feature but does not need it.
+	feature_maybe_unused: BTreeSet<CrateId>,
+	/// (Crate that is not forwarding the feature) -> (Dependency that enables it by default)
+	implicit_default: BTreeMap<CrateId, BTreeSet<FeatureDep>>,
+}
+
+impl Findings {
+	/// Check that `feature` is properly propagated by all crates in `to_check`.
+	///
+	/// Only dependencies of the given `kinds` need the feature.
+	fn check(feature: &str, to_check: &[PackageId], kinds: &[DepKind], meta: &Metadata) -> Self {
+		let mut findings = Self::default();
+		let selected =
+			|dep: &&Dependency| DepKind::of(dep.kind).is_some_and(|k| kinds.contains(&k));
+
+		for pkg in meta.packages.iter().filter(|pkg| to_check.contains(&pkg.id)) {
+			// TODO that it does not enable other features.
+
+			for dep in pkg.dependencies.iter().filter(selected) {
+				// Resolve the dep according to the metadata.
+				let resolved = resolve_dep(pkg, dep, meta);
+				// Features refer to renamed dependencies by their new name.
+				let key = dependency_key(dep);
+				let declarations =
+					pkg.dependencies.iter().filter(selected).filter(|d| dependency_key(d) == key);
+				// Optional deps can be declared multiple times, eg. as dev-dependency.
+				let optional = declarations.clone().any(|d| d.optional);
+				// Cargo unifies the default features of all declarations.
+				let uses_default = declarations.clone().any(|d| d.uses_default_features);
+
+				let Some(dep) = resolved else {
+					// Either outside workspace or not resolved, possibly due to not being used at
+					// all because of the target or whatever.
+					continue
+				};
+				if !dep.features.contains_key(feature) {
+					continue
+				}
+				let implicit = uses_default && enabled_by_default(&dep, feature);
+				let dep = FeatureDep { id: dep.id.to_string(), name: key.into(), optional };
+				let enabled = pkg.features.get(feature);
+
+				if enabled.is_some_and(|enabled| dep.is_propagated(feature, enabled)) {
+					continue
+				}
+				let findings = if implicit {
+					&mut findings.implicit_default
+				} else if enabled.is_none() {
+					&mut findings.feature_missing
+				} else {
+					&mut findings.propagate_missing
+				};
+				findings.entry(pkg.id.to_string()).or_default().insert(dep);
+			}
 		}
+
+		findings
+	}
+
+	/// Find the crates in `to_check` that have `feature` but do not use it.
+	///
+	/// This reads the sources of the crates, so it should only be done when the lint is enabled.
+	fn check_unused(&mut self, feature: &str, to_check: &[PackageId], meta: &Metadata) {
+		for pkg in meta.packages.iter().filter(|pkg| to_check.contains(&pkg.id)) {
+			if is_unused(pkg, feature, meta) {
+				self.feature_maybe_unused.insert(pkg.id.to_string());
+			}
+		}
+	}
+
+	/// All crates that have findings.
+	fn crates(&self) -> BTreeSet<&CrateId> {
+		self.propagate_missing
+			.keys()
+			.chain(self.feature_missing.keys())
+			.chain(self.implicit_default.keys())
+			.chain(self.feature_maybe_unused.iter())
+			.collect()
+	}
+
+	/// Remove the findings of `feature` that are silenced by the package configs.
+	///
+	/// Returns the removed findings as (crate, feature, kind, dependency) tuples.
+	fn suppress(
+		&mut self,
+		feature: &str,
+		allows: &BTreeMap<CrateId, PackageConfig>,
+	) -> Vec<(CrateId, String, FindingKind, Option<String>)> {
+		let allows = |krate: &CrateId, dep: Option<&str>| {
+			allows.get(krate).is_some_and(|config| config.allows(feature, dep))
+		};
+		let mut suppressed = Vec::new();
+		let kinds = [
+			(FindingKind::MissingPropagation, &mut self.propagate_missing),
+			(FindingKind::MissingFeature, &mut self.feature_missing),
+			(FindingKind::ImplicitDefault, &mut self.implicit_default),
+		];
+
+		for (kind, findings) in kinds {
+			for (krate, deps) in findings.iter_mut() {
+				deps.retain(|dep| {
+					let allowed = allows(krate, Some(&dep.name));
+					if allowed {
+						suppressed.push((
+							krate.clone(),
+							feature.into(),
+							kind,
+							Some(dep.name.clone()),
+						));
+					}
+					!allowed
+				});
+			}
+			findings.retain(|_, deps| !deps.is_empty());
+		}
+		self.feature_maybe_unused.retain(|krate| {
+			let allowed = allows(krate, None);
+			if allowed {
+				suppressed.push((krate.clone(), feature.into(), FindingKind::Unused, None));
+			}
+			!allowed
+		});
+
+		suppressed
+	}
+
+	/// Forget about the findings of lints that are disabled in `config`.
+	fn retain_enabled(&mut self, config: &Config) {
+		if !config.is_enabled(FindingKind::MissingPropagation) {
+			self.propagate_missing.clear();
+		}
+		if !config.is_enabled(FindingKind::MissingFeature) {
+			self.feature_missing.clear();
+		}
+		if !config.is_enabled(FindingKind::ImplicitDefault) {
+			self.implicit_default.clear();
+		}
+		if !config.is_enabled(FindingKind::Unused) {
+			self.feature_maybe_unused.clear();
+		}
+	}
+
+	/// Merge the findings of `other` into `self`.
+	fn extend(&mut self, other: Self) {
+		for (krate, deps) in other.propagate_missing {
+			self.propagate_missing.entry(krate).or_default().extend(deps);
+		}
+		for (krate, deps) in other.feature_missing {
+			self.feature_missing.entry(krate).or_default().extend(deps);
+		}
+		for (krate, deps) in other.implicit_default {
+			self.implicit_default.entry(krate).or_default().extend(deps);
+		}
+		self.feature_maybe_unused.extend(other.feature_maybe_unused);
+	}
+}
+
+/// Whether the `default` feature of `pkg` transitively enables its `feature`.
+fn enabled_by_default(pkg: &Package, feature: &str) -> bool {
+	let mut todo = vec!["default"];
+	let mut seen = BTreeSet::new();
+
+	while let Some(current) = todo.pop() {
+		if current == feature {
+			return true
+		}
+		if seen.insert(current) {
+			todo.extend(pkg.features.get(current).into_iter().flatten().map(String::as_str));
+		}
+	}
+
+	false
+}
+
+/// The canonical manifest path of `pkg`, or `None` if it is outside of `allowed_dir`.
+fn fixable_manifest(
+	pkg: &Package,
+	allowed_dir: &std::path::Path,
+) -> Result<Option<std::path::PathBuf>, String> {
+	let path = canonicalize(pkg.manifest_path.clone().into_std_path_buf())
+		.map_err(|e| format!("Failed to canonicalize manifest path: {e}"))?;
+	if !path.starts_with(allowed_dir) {
+		log::info!(
+			"Cannot fix {} because it is not in the allowed directory {}",
+			pkg.name,
+			allowed_dir.display()
+		);
+		return Ok(None)
+	}
+
+	Ok(Some(path))
+}
+
+/// Whether `feature` of `pkg` is not used at all.
+///
+/// This is the case if it does not enable anything, no dependant enables it and the code of the
+/// crate does not check for it. The `default` feature is never unused.
+fn is_unused(pkg: &Package, feature: &str, meta: &Metadata) -> bool {
+	if feature == "default" || !pkg.features.contains_key(feature) {
+		return false
+	}
+	// Follow the features of the crate itself to see whether any of them enables a dependency.
+	let mut todo = vec![feature];
+	let mut seen = BTreeSet::new();
+	while let Some(feature) = todo.pop() {
+		if !seen.insert(feature) {
+			continue
+		}
+		for entry in pkg.features.get(feature).into_iter().flatten() {
+			// Anything but another feature of the crate enables a dependency.
+			if !pkg.features.contains_key(entry) {
+				return false
+			}
+			todo.push(entry);
+		}
+	}
+
+	let enabled_by_dependant = meta.packages.iter().any(|dependant| {
+		dependant.dependencies.iter().any(|dep| {
+			let key = dependency_key(dep);
+			let entries = dependant.features.values().flatten();
+			let enables = dep.features.iter().any(|f| f == feature) ||
+				entries.into_iter().any(|e| {
+					e.split_once('/')
+						.is_some_and(|(d, f)| d.trim_end_matches('?') == key && f == feature)
+				});
+			enables && resolve_dep(dependant, dep, meta).is_some_and(|d| d.id == pkg.id)
+		})
+	});
+	if enabled_by_dependant {
+		return false
+	}
+
+	let dir = pkg.manifest_path.parent().expect("Manifests are in a directory");
+	!references_feature(dir.as_std_path(), feature)
+}
+
+/// Whether any rust file below `dir` checks for `feature`.
+///
+/// Looks for `feature = "name"` in `cfg` attributes and macros and for the `CARGO_FEATURE_NAME`
+/// variable that build scripts use.
+fn references_feature(dir: &std::path::Path, feature: &str) -> bool {
+	let cfg = Regex::new(&format!(r#"feature\s*=\s*"{}""#, regex::escape(feature)))
+		.expect("Regex is valid");
+	let env = format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"));
+	let mut todo = vec![dir.to_path_buf()];
+
+	while let Some(dir) = todo.pop() {
+		let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+
+		for path in entries.flatten().map(|e| e.path()) {
+			let name = path.file_name().unwrap_or_default().to_string_lossy();
+			if path.is_dir() {
+				if !name.starts_with('.') && name != "target" {
+					todo.push(path);
+				}
+			} else if name.ends_with(".rs") {
+				let Ok(code) = std::fs::read_to_string(&path) else { continue };
+				if cfg.is_match(&code) || code.contains(&env) {
+					return true
+				}
+			}
+		}
+	}
+
+	false
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn references_feature_works() {
+		let dir = tempfile::tempdir().unwrap();
+		std::fs::create_dir_all(dir.path().join("src/nested")).unwrap();
+		std::fs::create_dir_all(dir.path().join("target")).unwrap();
+		std::fs::write(dir.path().join("src/lib.rs"), "#[cfg(feature = \"std\")]\nmod a;").unwrap();
+		std::fs::write(dir.path().join("src/nested/a.rs"), "cfg!(feature=\"try-runtime\");")
+			.unwrap();
+		std::fs::write(
+			dir.path().join("build.rs"),
+			"env::var(\"CARGO_FEATURE_RUNTIME_BENCHMARKS\");",
+		)
+		.unwrap();
+		std::fs::write(dir.path().join("target/x.rs"), "#[cfg(feature = \"ignored\")]").unwrap();
+
+		assert!(references_feature(dir.path(), "std"));
+		assert!(references_feature(dir.path(), "try-runtime"));
+		assert!(references_feature(dir.path(), "runtime-benchmarks"));
+		assert!(!references_feature(dir.path(), "ignored"), "Skips the target dir");
+		assert!(!references_feature(dir.path(), "st"));
 	}
 }
@@ -4,9 +4,19 @@
 //! Sub-command definition and implementation.
 
 pub mod lint;
+pub mod rdeps;
 pub mod trace;
 
-use cargo_metadata::{Dependency, Metadata, MetadataCommand, Package, Resolve};
+use crate::{
+	cache::Cache,
+	dag::{Dag, EdgeLabel},
+	CrateId,
+};
+use cargo_metadata::{
+	CargoOpt, Dependency, DependencyKind, Metadata, MetadataCommand, Package, Resolve,
+};
+use cargo_platform::Cfg;
+use std::{collections::BTreeMap, fs::canonicalize};
 
 /// See out how Rust dependencies and features are enabled.
 #[derive(Debug, clap::Parser)]
@@ -22,11 +32,32 @@ pub struct Command {
 #[derive(Debug, clap::Subcommand)]
 enum SubCommand {
 	Trace(trace::TraceCmd),
+	Rdeps(rdeps::RdepsCmd),
 	Lint(lint::LintCmd),
 }
 
+/// Exit status of the program.
+///
+/// Lint commands use this to signal whether they found any problems, so that they can be used in
+/// CI directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+	/// Everything is fine.
+	Clean = 0,
+	/// A lint found problems.
+	Findings = 1,
+	/// Something went wrong internally.
+	Failure = 2,
+}
+
+impl From<Status> for std::process::ExitCode {
+	fn from(status: Status) -> Self {
+		(status as u8).into()
+	}
+}
+
 impl Command {
-	pub fn run(&self) {
+	pub fn run(&self) -> Result<Status, String> {
 		if self.quiet {
 			log::set_max_level(log::LevelFilter::Error);
 		} else {
@@ -34,7 +65,8 @@ impl Command {
 		}
 
 		match &self.subcommand {
-			SubCommand::Trace(cmd) => cmd.run(),
+			SubCommand::Trace(cmd) => cmd.run().map(|()| Status::Clean),
+			SubCommand::Rdeps(cmd) => cmd.run().map(|()| Status::Clean),
 			SubCommand::Lint(cmd) => cmd.run(),
 		}
 	}
@@ -56,30 +88,320 @@ pub struct TreeArgs {
 	/// Whether to use offline mode.
 	#[clap(long, global = true)]
 	pub offline: bool,
+
+	/// Only consider these kinds of dependencies.
+	///
+	/// Can be comma separated, eg. `--dep-kinds normal,build`. Defaults to all kinds.
+	#[clap(long, global = true, value_enum, value_delimiter = ',')]
+	pub dep_kinds: Vec<DepKind>,
+
+	/// Only consider dependencies that are used when building for this target triple.
+	///
+	/// For example `wasm32-unknown-unknown` to see what ends up in a runtime build.
+	#[clap(long, global = true)]
+	pub target: Option<String>,
+
+	/// Activate these features when resolving the dependencies.
+	///
+	/// Can be comma separated. Pass `--features default` to analyze a plain `cargo build`.
+	/// Defaults to all features, so that every optional dependency shows up.
+	#[clap(long = "features", id = "crate_features", global = true, value_delimiter = ',')]
+	pub crate_features: Vec<String>,
+
+	/// Activate all features when resolving the dependencies.
+	#[clap(long, global = true, conflicts_with_all = ["crate_features", "no_default_features"])]
+	pub all_features: bool,
+
+	/// Do not activate the `default` feature when resolving the dependencies.
+	#[clap(long, global = true)]
+	pub no_default_features: bool,
+
+	/// Read the output of `cargo metadata --format-version 1` from this file instead of running
+	/// cargo. Pass `-` to read it from stdin.
+	#[clap(
+		long,
+		global = true,
+		conflicts_with_all = ["workspace", "offline", "crate_features", "all_features", "no_default_features"],
+	)]
+	pub metadata_file: Option<std::path::PathBuf>,
+
+	/// Do not use the cached metadata in the target directory.
+	#[clap(long, global = true)]
+	pub no_cache: bool,
+}
+
+/// The kind of a dependency.
+#[derive(
+	Debug,
+	Clone,
+	Copy,
+	PartialEq,
+	Eq,
+	PartialOrd,
+	Ord,
+	clap::ValueEnum,
+	serde::Serialize,
+	serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum DepKind {
+	/// A `[dependencies]` entry.
+	Normal,
+	/// A `[build-dependencies]` entry.
+	Build,
+	/// A `[dev-dependencies]` entry.
+	Dev,
+}
+
+impl DepKind {
+	/// The kind of a cargo dependency, if it is known.
+	pub fn of(kind: DependencyKind) -> Option<Self> {
+		match kind {
+			DependencyKind::Normal => Some(Self::Normal),
+			DependencyKind::Build => Some(Self::Build),
+			DependencyKind::Development => Some(Self::Dev),
+			_ => None,
+		}
+	}
+}
+
+impl EdgeLabel for DepKind {
+	/// Only the crate itself builds its dev-dependencies; its dependants never do.
+	fn first_hop_only(&self) -> bool {
+		*self == Self::Dev
+	}
+}
+
+impl std::fmt::Display for DepKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Normal => "normal",
+			Self::Build => "build",
+			Self::Dev => "dev",
+		}
+		.fmt(f)
+	}
 }
 
 impl TreeArgs {
 	/// Load the metadata of the rust project.
 	pub fn load_metadata(&self) -> Result<Metadata, String> {
-		let mut cmd = MetadataCommand::new();
-		let manifest_path = if self.manifest_path.is_dir() {
+		let mut meta = match &self.metadata_file {
+			Some(path) => Self::read_metadata(path)?,
+			None => self.cached_metadata()?,
+		};
+		if !self.dep_kinds.is_empty() {
+			self.retain_dep_kinds(&mut meta);
+		}
+		if let Some(target) = &self.target {
+			// Cargo only filters the resolve graph, but not the declared dependencies.
+			let cfgs = target_cfgs(target)?;
+			for pkg in meta.packages.iter_mut() {
+				pkg.dependencies
+					.retain(|dep| dep.target.as_ref().map_or(true, |t| t.matches(target, &cfgs)));
+			}
+		}
+		Ok(meta)
+	}
+
+	/// The manifest of the workspace.
+	pub fn manifest_path(&self) -> std::path::PathBuf {
+		if self.manifest_path.is_dir() {
 			self.manifest_path.join("Cargo.toml")
 		} else {
 			self.manifest_path.clone()
+		}
+	}
+
+	/// The directory that fixes are allowed to write to.
+	///
+	/// This is the directory of the manifest or the workspace root of a metadata file.
+	pub fn allowed_dir(&self, meta: &Metadata) -> Result<std::path::PathBuf, String> {
+		if self.metadata_file.is_some() {
+			return Ok(meta.workspace_root.clone().into_std_path_buf())
+		}
+		let manifest = canonicalize(self.manifest_path())
+			.map_err(|e| format!("Failed to canonicalize manifest path: {e}"))?;
+		Ok(manifest.parent().expect("Manifests are in a directory").to_path_buf())
+	}
+
+	/// Read the output of `cargo metadata --format-version 1` from a file or stdin for `-`.
+	fn read_metadata(path: &std::path::Path) -> Result<Metadata, String> {
+		let raw = if path == std::path::Path::new("-") {
+			std::io::read_to_string(std::io::stdin())
+				.map_err(|e| format!("Failed to read metadata from stdin: {e}"))?
+		} else {
+			std::fs::read_to_string(path)
+				.map_err(|e| format!("Failed to read metadata file {}: {e}", path.display()))?
 		};
+
+		MetadataCommand::parse(raw).map_err(|e| format!("Failed to parse metadata: {e}"))
+	}
+
+	/// Run `cargo metadata` on the manifest or use the cached result of a previous run.
+	fn cached_metadata(&self) -> Result<Metadata, String> {
+		if self.no_cache {
+			return self.exec_metadata()
+		}
+		let manifest = canonicalize(self.manifest_path())
+			.map_err(|e| format!("Failed to canonicalize manifest path: {e}"))?;
+		let cache = Cache::for_manifest(&manifest);
+		// Everything that is passed to cargo.
+		let key = format!(
+			"{manifest:?} {} {} {:?} {} {} {:?}",
+			self.workspace,
+			self.offline,
+			self.crate_features,
+			self.all_features,
+			self.no_default_features,
+			self.target
+		);
+
+		if let Some(meta) = cache.load(&key) {
+			log::info!("Using cached metadata");
+			return Ok(meta)
+		}
+		let meta = self.exec_metadata()?;
+		if let Err(e) = cache.store(&key, &meta) {
+			log::warn!("{e}");
+		}
+		Ok(meta)
+	}
+
+	/// Run `cargo metadata` on the manifest.
+	fn exec_metadata(&self) -> Result<Metadata, String> {
+		let mut cmd = MetadataCommand::new();
+		let manifest_path = self.manifest_path();
 		log::debug!("Using manifest path: {:?}", manifest_path);
 		cmd.manifest_path(&manifest_path);
-		cmd.features(cargo_metadata::CargoOpt::AllFeatures);
+		if self.uses_all_features() {
+			cmd.features(CargoOpt::AllFeatures);
+		}
+		if self.no_default_features {
+			cmd.features(CargoOpt::NoDefaultFeatures);
+		}
+		// The `default` feature is already active unless it was disabled.
+		let features = self
+			.crate_features
+			.iter()
+			.filter(|f| self.no_default_features || f.as_str() != "default")
+			.cloned()
+			.collect::<Vec<_>>();
+		if !features.is_empty() {
+			cmd.features(CargoOpt::SomeFeatures(features));
+		}
+		log::info!("Using {}", self.feature_selection());
 
 		if self.workspace {
 			cmd.no_deps();
 		}
+		let mut options = Vec::new();
 		if self.offline {
-			cmd.other_options(vec!["--offline".to_string()]);
+			options.push("--offline".to_string());
+		}
+		if let Some(target) = &self.target {
+			options.extend(["--filter-platform".to_string(), target.clone()]);
 		}
+		cmd.other_options(options);
 
 		cmd.exec().map_err(|e| format!("Failed to load metadata: {e}"))
 	}
+
+	/// Whether all features are activated; either explicitly or because none were selected.
+	fn uses_all_features(&self) -> bool {
+		self.all_features || (self.crate_features.is_empty() && !self.no_default_features)
+	}
+
+	/// Human readable description of the features that the analysis is based on.
+	pub fn feature_selection(&self) -> String {
+		match &self.metadata_file {
+			Some(path) if path == std::path::Path::new("-") =>
+				return "the features of the metadata from stdin".into(),
+			Some(path) => return format!("the features of metadata file {}", path.display()),
+			None => (),
+		}
+		if self.uses_all_features() {
+			return "all features".into()
+		}
+		let base =
+			if self.no_default_features { "no default features" } else { "default features" };
+		let extra = self
+			.crate_features
+			.iter()
+			.filter(|f| self.no_default_features || f.as_str() != "default")
+			.map(String::as_str)
+			.collect::<Vec<_>>();
+
+		if extra.is_empty() {
+			base.into()
+		} else {
+			format!("{base} and {}", extra.join(", "))
+		}
+	}
+
+	/// Remove all dependencies from `meta` whose kind was not selected.
+	fn retain_dep_kinds(&self, meta: &mut Metadata) {
+		let selected =
+			|kind: DependencyKind| DepKind::of(kind).is_some_and(|k| self.dep_kinds.contains(&k));
+
+		for pkg in meta.packages.iter_mut() {
+			pkg.dependencies.retain(|dep| selected(dep.kind));
+		}
+		for node in meta.resolve.iter_mut().flat_map(|r| r.nodes.iter_mut()) {
+			node.deps.retain_mut(|dep| {
+				dep.dep_kinds.retain(|info| selected(info.kind));
+				!dep.dep_kinds.is_empty()
+			});
+			node.dependencies = node.deps.iter().map(|dep| dep.pkg.clone()).collect();
+		}
+	}
+}
+
+/// The `cfg` values of a target triple as reported by `rustc`.
+fn target_cfgs(target: &str) -> Result<Vec<Cfg>, String> {
+	let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+	let output = std::process::Command::new(rustc)
+		.args(["--print", "cfg", "--target", target])
+		.output()
+		.map_err(|e| format!("Failed to run rustc: {e}"))?;
+	if !output.status.success() {
+		return Err(format!(
+			"Failed to get the cfg of target {target}: {}",
+			String::from_utf8_lossy(&output.stderr).trim()
+		))
+	}
+
+	String::from_utf8_lossy(&output.stdout)
+		.lines()
+		.map(|line| line.parse().map_err(|e| format!("Failed to parse cfg {line:?}: {e}")))
+		.collect()
+}
+
+/// Dependency graph over crate ids whose edges are labeled with the kinds of the dependency.
+pub(crate) type CrateDag = Dag<CrateId, DepKind>;
+
+/// Build a dependency graph over the crates ids and return an index of all crates.
+pub(crate) fn build_dag(meta: &Metadata) -> Result<(CrateDag, BTreeMap<CrateId, Package>), String> {
+	let mut dag = Dag::new();
+	let mut index = BTreeMap::new();
+
+	for pkg in meta.packages.iter() {
+		let id = pkg.id.to_string();
+		dag.add_node(id.clone());
+		index.insert(pkg.id.to_string(), pkg.clone());
+
+		for dep in pkg.dependencies.iter() {
+			if let Some(resolved) = resolve_dep(pkg, dep, meta) {
+				let did = resolved.id.to_string();
+				match DepKind::of(dep.kind) {
+					Some(kind) => dag.add_labeled_edge(id.clone(), did, kind),
+					None => dag.add_edge(id.clone(), did),
+				}
+			}
+		}
+	}
+
+	Ok((dag, index))
 }
 
 /// Resolve the dependency `dep` of `pkg` within the metadata.
@@ -113,10 +435,66 @@ pub(crate) fn resolve_dep_from_graph(
 	dep: &Dependency,
 	(meta, resolve): (&Metadata, &Resolve),
 ) -> Option<Package> {
-	let dep_name = dep.name.replace('-', "_");
+	// The resolve graph uses the renamed library name.
+	let dep_name = dependency_key(dep).replace('-', "_");
 	let resolved_pkg = resolve.nodes.iter().find(|node| node.id == pkg.id)?;
-	let resolved_dep_id = resolved_pkg.deps.iter().find(|node| node.name == dep_name)?;
-	let resolve_dep = meta.packages.iter().find(|pkg| pkg.id == resolved_dep_id.pkg)?;
+	let candidates = resolved_pkg
+		.deps
+		.iter()
+		.filter_map(|node| {
+			let candidate = meta.packages.iter().find(|pkg| pkg.id == node.pkg)?;
+			(candidate.name == dep.name).then_some((node, candidate))
+		})
+		.collect::<Vec<_>>();
+	// A package can be a dependency multiple times under different names and versions.
+	let resolve_dep = candidates
+		.iter()
+		.find(|(node, c)| node.name == dep_name && dep.req.matches(&c.version))
+		.or_else(|| candidates.iter().find(|(node, _)| node.name == dep_name))
+		.or_else(|| candidates.first())?;
+
+	Some(resolve_dep.1.clone())
+}
+
+/// The name under which a crate refers to its dependency `dep` in its manifest and features.
+pub(crate) fn dependency_key(dep: &Dependency) -> &str {
+	dep.rename.as_deref().unwrap_or(&dep.name)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use cargo_platform::Platform;
+
+	#[rstest::rstest]
+	#[case(&[], "all features")]
+	#[case(&["--all-features"], "all features")]
+	#[case(&["--features", "default"], "default features")]
+	#[case(&["--features", "std,runtime-benchmarks"], "default features and std, runtime-benchmarks")]
+	#[case(&["--no-default-features"], "no default features")]
+	#[case(&["--no-default-features", "--features", "default,std"], "no default features and default, std")]
+	fn feature_selection_works(#[case] args: &[&str], #[case] want: &str) {
+		use clap::Parser;
+		let args = TreeArgs::try_parse_from(std::iter::once("test").chain(args.iter().copied()));
+
+		assert_eq!(args.unwrap().feature_selection(), want);
+	}
+
+	#[test]
+	fn all_features_conflicts() {
+		use clap::Parser;
+
+		assert!(TreeArgs::try_parse_from(["test", "--all-features", "--features", "std"]).is_err());
+	}
+
+	#[test]
+	fn target_cfgs_work() {
+		let cfgs = target_cfgs("wasm32-unknown-unknown").unwrap();
+		let platform = |raw: &str| raw.parse::<Platform>().unwrap();
 
-	Some(resolve_dep.clone())
+		assert!(platform(r#"cfg(target_arch = "wasm32")"#).matches("wasm32-unknown-unknown", &cfgs));
+		assert!(!platform("cfg(unix)").matches("wasm32-unknown-unknown", &cfgs));
+		assert!(platform("wasm32-unknown-unknown").matches("wasm32-unknown-unknown", &cfgs));
+		assert!(target_cfgs("no-such-target").is_err());
+	}
 }
@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// SPDX-FileCopyrightText: Oliver Tale-Yazdi <oliver@tasty.limo>
+
+//! List the workspace crates that depend on a crate.
+
+use super::*;
+use clap::Parser;
+
+/// List the workspace crates that depend on a crate.
+#[derive(Debug, Parser)]
+pub struct RdepsCmd {
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	tree_args: super::TreeArgs,
+
+	/// Also list the crates that depend on it indirectly.
+	///
+	/// Crates that only reach it through the dev-dependencies of a dependency are not listed.
+	#[clap(long)]
+	transitive: bool,
+
+	/// Only list crates that depend on it via at most this many hops.
+	///
+	/// Implies `--transitive`.
+	#[clap(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+	depth: Option<usize>,
+
+	/// Show the version of the crates in the output.
+	#[clap(long)]
+	show_version: bool,
+
+	/// The dependency crate.
+	#[clap(index(1), value_name = "CRATE")]
+	krate: String,
+}
+
+impl RdepsCmd {
+	pub(crate) fn run(&self) -> Result<(), String> {
+		let meta = self.tree_args.load_metadata()?;
+		println!("Using {}", self.tree_args.feature_selection());
+		let (dag, index) = build_dag(&meta)?;
+
+		let ids = index
+			.iter()
+			.filter(|(_id, krate)| krate.name == self.krate)
+			.map(|(id, _)| id)
+			.collect::<Vec<_>>();
+		if ids.is_empty() {
+			return Err(format!("Could not find crate {} in the dependency graph", self.krate))
+		}
+
+		let depth = match (self.depth, self.transitive) {
+			(Some(depth), _) => Some(depth),
+			(None, true) => None,
+			(None, false) => Some(1),
+		};
+		// Crate -> Smallest number of hops to any of the `ids`.
+		let mut found = BTreeMap::<&CrateId, usize>::new();
+		for id in ids.iter() {
+			for (dependant, hops) in dag.dependants(id, depth) {
+				let min = found.entry(dependant).or_insert(hops);
+				*min = (*min).min(hops);
+			}
+		}
+
+		let mut found = found
+			.into_iter()
+			.filter(|(id, _)| !ids.contains(id))
+			.map(|(id, hops)| (hops, &index[id]))
+			.filter(|(_, krate)| meta.workspace_members.contains(&krate.id))
+			.collect::<Vec<_>>();
+		found.sort_by(|(a_hops, a), (b_hops, b)| (a_hops, &a.name).cmp(&(b_hops, &b.name)));
+
+		if found.is_empty() {
+			println!("No workspace crate depends on {}", self.krate);
+			return Ok(())
+		}
+		println!("Found {} workspace crates that depend on {}:", found.len(), self.krate);
+		for (hops, krate) in found {
+			let mut out = format!("  {}", krate.name);
+			if self.show_version {
+				out.push_str(&format!(" v{}", krate.version));
+			}
+			if depth != Some(1) {
+				out.push_str(&format!(" ({hops} {})", if hops == 1 { "hop" } else { "hops" }));
+			}
+			println!("{out}");
+		}
+
+		Ok(())
+	}
+}
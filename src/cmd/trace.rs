@@ -4,10 +4,9 @@
 //! Trace the dependency path from one crate to another.
 
 use super::*;
-use crate::{dag::Dag, CrateId};
-use cargo_metadata::{Metadata, Package};
+use crate::dag::{Avoid, Path};
 use clap::Parser;
-use std::collections::{BTreeMap, BTreeSet};
+use std::{borrow::Cow, collections::BTreeSet};
 
 /// Trace the dependency path from one crate to another.
 #[derive(Debug, Parser)]
@@ -24,6 +23,10 @@ pub struct TraceCmd {
 	#[clap(long)]
 	show_version: bool,
 
+	/// Show the kinds of the dependencies in the output.
+	#[clap(long)]
+	show_kind: bool,
+
 	/// Delimiter for rendering dependency paths.
 	#[clap(long, default_value = " -> ")]
 	path_delimiter: String,
@@ -35,6 +38,46 @@ pub struct TraceCmd {
 	#[clap(long)]
 	unique_versions: bool,
 
+	/// Return any path instead of the shortest one.
+	///
+	/// This is faster but gives no guarantee about which path is returned.
+	#[clap(long, conflicts_with_all = ["all", "limit"])]
+	any: bool,
+
+	/// Return all paths instead of only the shortest one.
+	///
+	/// Their number can grow exponentially with the size of the workspace.
+	#[clap(long, conflicts_with = "limit")]
+	all: bool,
+
+	/// Return up to this many of the shortest paths, sorted by their length.
+	#[clap(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+	limit: Option<usize>,
+
+	/// Shorten paths that end like an earlier path to the part that differs.
+	///
+	/// The omitted rest is rendered as `...`.
+	#[clap(long)]
+	collapse: bool,
+
+	/// Only consider paths that do not go through these crates.
+	///
+	/// Can be comma separated. Reports whether avoiding them disconnects the two crates.
+	#[clap(long, value_delimiter = ',', value_name = "CRATE", conflicts_with = "any")]
+	avoid: Vec<String>,
+
+	/// Only consider paths that do not use these dependencies.
+	///
+	/// Can be comma separated, eg. `--avoid-edge libp2p:libp2p-webrtc`.
+	#[clap(long, value_delimiter = ',', value_name = "FROM:TO", conflicts_with = "any")]
+	avoid_edge: Vec<String>,
+
+	/// Report the fewest dependencies that need to be removed to disconnect the two crates.
+	///
+	/// Each dependency is listed with the manifest that declares it.
+	#[clap(long, conflicts_with_all = ["any", "all", "limit", "collapse", "avoid", "avoid_edge"])]
+	cut: bool,
+
 	/// The root crate to start from.
 	#[clap(index(1))]
 	from: String,
@@ -45,99 +88,200 @@ pub struct TraceCmd {
 }
 
 impl TraceCmd {
-	pub(crate) fn run(&self) {
-		let meta = self.tree_args.load_metadata().expect("Loads metadata");
-		let (dag, index) = self.build_dag(meta).expect("Builds dependency graph");
+	pub(crate) fn run(&self) -> Result<(), String> {
+		let meta = self.tree_args.load_metadata()?;
+		println!("Using {}", self.tree_args.feature_selection());
+		let (dag, index) = build_dag(&meta)?;
 		let lookup = |id: &str| {
 			index
 				.get(id)
-				.unwrap_or_else(|| panic!("Could not find crate {id} in the metadata"))
+				.ok_or_else(|| format!("Could not find crate {id} in the metadata"))
 		};
 
-		let froms = index
-			.iter()
-			.filter(|(_id, krate)| krate.name == self.from)
-			.map(|(id, _)| id)
-			.collect::<Vec<_>>();
+		let ids_of = |name: &str| {
+			index
+				.iter()
+				.filter(|(_id, krate)| krate.name == name)
+				.map(|(id, _)| id)
+				.collect::<Vec<_>>()
+		};
+
+		let froms = ids_of(&self.from);
 		if froms.is_empty() {
-			panic!("Could not find crate {} in the left dependency graph", self.from);
+			return Err(format!("Could not find crate {} in the left dependency graph", self.from))
 		}
 
-		let tos = index
-			.iter()
-			.filter(|(_id, krate)| krate.name == self.to)
-			.map(|(id, _)| id)
-			.collect::<Vec<_>>();
+		let tos = ids_of(&self.to);
 		if tos.is_empty() {
-			panic!("Could not find crate {} in the right dependency graph", self.to);
+			return Err(format!("Could not find crate {} in the right dependency graph", self.to))
+		}
+
+		let mut avoid = Avoid::default();
+		for name in self.avoid.iter() {
+			if name == &self.from || name == &self.to {
+				return Err(format!("Cannot avoid the traced crate {name}"))
+			}
+			let ids = ids_of(name);
+			if ids.is_empty() {
+				return Err(format!("Could not find avoided crate {name} in the dependency graph"))
+			}
+			avoid.nodes.extend(ids);
+		}
+		for edge in self.avoid_edge.iter() {
+			let (from, to) = edge
+				.split_once(':')
+				.ok_or_else(|| format!("Invalid avoided edge {edge}, expected FROM:TO"))?;
+			let (from, to) = (ids_of(from), ids_of(to));
+			let edges = from
+				.iter()
+				.flat_map(|from| to.iter().map(move |to| (*from, *to)))
+				.filter(|(from, to)| dag.connected(from, to))
+				.collect::<Vec<_>>();
+			if edges.is_empty() {
+				return Err(format!("Could not find avoided edge {edge} in the dependency graph"))
+			}
+			avoid.edges.extend(edges);
 		}
 
 		log::info!(
 			"No version or features specified: Checking all {} possibly distinct paths",
 			froms.len() * tos.len()
 		);
-		let mut paths = BTreeSet::new();
+		// Unescape the delimiter.
+		let delimiter = self.path_delimiter.replace("\\n", "\n").replace("\\t", "\t");
+		if self.cut {
+			return self.print_cut(&froms, &tos, &dag, &lookup, &delimiter)
+		}
+		// All suffixes of the printed paths.
+		let mut printed = BTreeSet::<Vec<CrateId>>::new();
+		let mut found = 0;
 
 		for from in froms.iter() {
 			for to in tos.iter() {
-				if let Some(path) = dag.any_path(from, to) {
-					paths.insert(path);
+				let paths: Box<dyn Iterator<Item = Path<CrateId>>> = if self.all {
+					Box::new(dag.all_paths_avoiding(from, to, avoid.clone()))
+				} else if let Some(limit) = self.limit {
+					Box::new(dag.k_shortest_paths_avoiding(from, to, limit, &avoid).into_iter())
+				} else if self.any {
+					Box::new(dag.any_path(from, to).into_iter())
+				} else {
+					Box::new(dag.shortest_path_avoiding(from, to, &avoid).into_iter())
+				};
+
+				for path in paths {
+					found += 1;
+					let nodes = path.0.into_iter().map(Cow::into_owned).collect::<Vec<_>>();
+					let shared = if self.collapse { shared_suffix(&nodes, &printed) } else { None };
+					let end = shared.map_or(nodes.len(), |start| start + 1);
+
+					let mut out = self.render(&nodes[..end], &dag, &lookup, &delimiter)?;
+					if end < nodes.len() {
+						out.push_str(&delimiter);
+						out.push_str("...");
+					}
+					println!("{out}");
+
+					printed.extend((0..nodes.len()).map(|i| nodes[i..].to_vec()));
 				}
 			}
 		}
-		if paths.is_empty() {
-			panic!("No path found");
+		if found == 0 && !avoid.is_empty() {
+			let connected = froms
+				.iter()
+				.any(|from| tos.iter().any(|to| dag.shortest_path(from, to).is_some()));
+			if connected {
+				let avoided = self.avoid.iter().chain(self.avoid_edge.iter());
+				println!(
+					"Avoiding {} disconnects {} from {}",
+					avoided.cloned().collect::<Vec<_>>().join(", "),
+					self.from,
+					self.to
+				);
+				return Ok(())
+			}
 		}
-		log::info!("Found {} distinct paths", paths.len());
-		// Unescape the delimiter.
-		let delimiter = self.path_delimiter.replace("\\n", "\n").replace("\\t", "\t");
+		if found == 0 {
+			return Err(format!("No path found from {} to {}", self.from, self.to))
+		}
+		log::info!("Found {found} distinct paths");
 
-		for path in paths {
-			let mut out = String::new();
-			let mut is_first = true;
+		Ok(())
+	}
 
-			path.for_each(|id| {
-				let krate = lookup(id);
-				if !is_first {
-					out.push_str(&delimiter);
-				}
-				is_first = false;
-				out.push_str(&krate.name);
-				if self.show_version {
-					out.push_str(&format!(" v{}", krate.version));
-				}
-				if self.show_source {
-					if let Some(source) = krate.source.as_ref() {
-						out.push_str(&format!(" ({})", source.repr));
-					}
-				}
-			});
+	/// Print the minimal cut between all `froms` and `tos`.
+	fn print_cut<'a>(
+		&self,
+		froms: &[&CrateId],
+		tos: &[&CrateId],
+		dag: &CrateDag,
+		lookup: &impl Fn(&str) -> Result<&'a Package, String>,
+		delimiter: &str,
+	) -> Result<(), String> {
+		let mut cut = BTreeSet::new();
+		for from in froms.iter() {
+			for to in tos.iter() {
+				let pair = dag
+					.min_cut(from, to)
+					.ok_or_else(|| format!("Cannot disconnect {} from itself", self.from))?;
+				cut.extend(pair);
+			}
+		}
+		if cut.is_empty() {
+			return Err(format!("No path found from {} to {}", self.from, self.to))
+		}
 
-			println!("{out}");
+		println!(
+			"Removing these {} dependencies disconnects {} from {}:",
+			cut.len(),
+			self.from,
+			self.to
+		);
+		for (dependant, dependency) in cut {
+			let edge = [dependant.clone(), dependency.clone()];
+			let manifest = &lookup(dependant)?.manifest_path;
+			println!("  {} in {manifest}", self.render(&edge, dag, lookup, delimiter)?);
 		}
+
+		Ok(())
 	}
 
-	/// Build a dependency graph over the crates ids and return an index of all crates.
-	fn build_dag(
+	/// Render the crates of a path.
+	fn render<'a>(
 		&self,
-		meta: Metadata,
-	) -> Result<(Dag<CrateId>, BTreeMap<CrateId, Package>), String> {
-		let mut dag = Dag::new();
-		let mut index = BTreeMap::new();
-
-		for pkg in meta.packages.clone().into_iter() {
-			let id = pkg.id.to_string();
-			dag.add_node(id.clone());
-			index.insert(pkg.id.to_string(), pkg.clone());
-
-			for dep in pkg.dependencies.iter() {
-				if let Some(dep) = resolve_dep(&pkg, dep, &meta) {
-					let did = dep.id.to_string();
-					dag.add_edge(id.clone(), did);
+		nodes: &[CrateId],
+		dag: &CrateDag,
+		lookup: &impl Fn(&str) -> Result<&'a Package, String>,
+		delimiter: &str,
+	) -> Result<String, String> {
+		let mut out = String::new();
+
+		for (i, id) in nodes.iter().enumerate() {
+			let krate = lookup(id)?;
+			if i > 0 {
+				out.push_str(delimiter);
+				if self.show_kind {
+					let kinds = dag.labels(&nodes[i - 1], id).map(|k| k.to_string());
+					out.push_str(&format!("[{}] ", kinds.collect::<Vec<_>>().join(", ")));
+				}
+			}
+			out.push_str(&krate.name);
+			if self.show_version {
+				out.push_str(&format!(" v{}", krate.version));
+			}
+			if self.show_source {
+				if let Some(source) = krate.source.as_ref() {
+					out.push_str(&format!(" ({})", source.repr));
 				}
 			}
 		}
 
-		Ok((dag, index))
+		Ok(out)
 	}
 }
+
+/// Start of the longest suffix of `nodes` that was already `printed`.
+///
+/// The suffix must contain at least one edge, and the start is never the first node.
+fn shared_suffix(nodes: &[CrateId], printed: &BTreeSet<Vec<CrateId>>) -> Option<usize> {
+	(1..nodes.len().saturating_sub(1)).find(|&i| printed.contains(&nodes[i..]))
+}
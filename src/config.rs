@@ -0,0 +1,294 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// SPDX-FileCopyrightText: Oliver Tale-Yazdi <oliver@tasty.limo>
+
+//! Lint configuration that lives in the workspace.
+//!
+//! It is either read from a `feature.toml` in the workspace root or from the
+//! `[workspace.metadata.feature]` table of the root manifest:
+//!
+//! ```toml
+//! [workspace.metadata.feature]
+//! features = ["std", "runtime-benchmarks", "try-runtime"]
+//! exclude = ["node-cli"]
+//! lints = ["missing-feature", "missing-propagation", "implicit-default"]
+//!
+//! # Kinds of dependencies that a feature is propagated to. Defaults to normal and dev.
+//! [workspace.metadata.feature.dep-kinds]
+//! std = ["normal"]
+//! ```
+//!
+//! Single crates can silence findings in their `[package.metadata.feature]` table or with an
+//! `# feature: allow(..)` comment inside of the feature:
+//!
+//! ```toml
+//! [package.metadata.feature]
+//! allow = [{ feature = "std", dependency = "sp-io" }, { feature = "try-runtime" }]
+//!
+//! [features]
+//! std = [
+//!     # feature: allow(sp-core)
+//! ]
+//! ```
+
+use crate::cmd::{lint::FindingKind, DepKind};
+use cargo_metadata::{Metadata, Package};
+use regex::Regex;
+use std::{collections::BTreeMap, path::Path};
+
+/// Name of the config file in the workspace root.
+pub const CONFIG_FILE: &str = "feature.toml";
+
+/// Kinds of dependencies that features are propagated to, unless configured otherwise.
+///
+/// Build dependencies are compiled for the host and do not share features with the crate.
+pub const DEFAULT_DEP_KINDS: [DepKind; 2] = [DepKind::Normal, DepKind::Dev];
+
+/// Lint settings of a workspace.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+	/// Features that must be propagated.
+	pub features: Vec<String>,
+	/// Names of packages that are never checked.
+	pub exclude: Vec<String>,
+	/// Kinds of findings that are reported. The `unused` lint is disabled by default.
+	pub lints: Vec<FindingKind>,
+	/// Feature -> Kinds of dependencies that it must be propagated to.
+	pub dep_kinds: BTreeMap<String, Vec<DepKind>>,
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			features: Vec::new(),
+			exclude: Vec::new(),
+			lints: vec![
+				FindingKind::MissingFeature,
+				FindingKind::MissingPropagation,
+				FindingKind::ImplicitDefault,
+			],
+			dep_kinds: BTreeMap::new(),
+		}
+	}
+}
+
+impl Config {
+	/// Load the config of the workspace described by `meta`.
+	///
+	/// Prefers the `feature.toml` over the workspace metadata and falls back to the default.
+	pub fn load(meta: &Metadata) -> Result<Self, String> {
+		let file = meta.workspace_root.join(CONFIG_FILE);
+		let metadata = meta.workspace_metadata.get("feature");
+
+		if file.exists() {
+			if metadata.is_some() {
+				log::warn!("Ignoring [workspace.metadata.feature] in favour of {file}");
+			}
+			return Self::from_file(file.as_std_path())
+		}
+		match metadata {
+			Some(metadata) => Self::from_metadata(metadata.clone()),
+			None => Ok(Self::default()),
+		}
+	}
+
+	/// Read the config from a TOML file.
+	pub fn from_file(path: &Path) -> Result<Self, String> {
+		let raw = std::fs::read_to_string(path)
+			.map_err(|e| format!("Failed to read config {}: {e}", path.display()))?;
+		Self::from_toml(&raw).map_err(|e| format!("Failed to parse config {}: {e}", path.display()))
+	}
+
+	/// Parse the config from TOML.
+	pub fn from_toml(raw: &str) -> Result<Self, String> {
+		toml_edit::de::from_str(raw).map_err(|e| e.to_string())
+	}
+
+	/// Parse the config from the `[workspace.metadata.feature]` table.
+	pub fn from_metadata(metadata: serde_json::Value) -> Result<Self, String> {
+		serde_json::from_value(metadata)
+			.map_err(|e| format!("Failed to parse [workspace.metadata.feature]: {e}"))
+	}
+
+	/// Whether findings of `kind` are reported.
+	pub fn is_enabled(&self, kind: FindingKind) -> bool {
+		self.lints.contains(&kind)
+	}
+
+	/// The kinds of dependencies that `feature` must be propagated to.
+	pub fn dep_kinds(&self, feature: &str) -> &[DepKind] {
+		self.dep_kinds.get(feature).map_or(&DEFAULT_DEP_KINDS, Vec::as_slice)
+	}
+}
+
+/// Lint settings of a single package.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PackageConfig {
+	/// Findings that are silenced.
+	pub allow: Vec<Allow>,
+}
+
+/// Silences the findings of a feature.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Allow {
+	/// The feature of the package.
+	pub feature: String,
+	/// Only silence findings about this dependency. Silences all findings if `None`.
+	#[serde(default)]
+	pub dependency: Option<String>,
+}
+
+impl PackageConfig {
+	/// Load the config of `pkg` from its metadata and the comment directives in its manifest.
+	pub fn load(pkg: &Package) -> Result<Self, String> {
+		let mut config = match pkg.metadata.get("feature") {
+			Some(metadata) => serde_json::from_value(metadata.clone()).map_err(|e| {
+				format!("Failed to parse [package.metadata.feature] of {}: {e}", pkg.name)
+			})?,
+			None => Self::default(),
+		};
+		let raw = std::fs::read_to_string(&pkg.manifest_path)
+			.map_err(|e| format!("Failed to read manifest {}: {e}", pkg.manifest_path))?;
+		config.allow.extend(Self::directives(&raw)?);
+
+		Ok(config)
+	}
+
+	/// Parse the `# feature: allow(dep, ..)` comments inside the features of a raw manifest.
+	///
+	/// A directive without dependencies silences all findings of the feature.
+	pub fn directives(raw: &str) -> Result<Vec<Allow>, String> {
+		let doc = raw
+			.parse::<toml_edit::Document>()
+			.map_err(|e| format!("Failed to parse manifest: {e}"))?;
+		let Some(features) = doc.get("features").and_then(|f| f.as_table_like()) else {
+			return Ok(Vec::new())
+		};
+		let directive =
+			Regex::new(r"#\s*feature:\s*allow(?:\(([^)]*)\))?").expect("Regex is valid");
+		let mut allow = Vec::new();
+
+		for (feature, item) in features.iter() {
+			let Some(value) = item.as_value() else { continue };
+			let mut comments = vec![value.decor().suffix()];
+			if let Some(array) = value.as_array() {
+				comments
+					.extend(array.iter().flat_map(|v| [v.decor().prefix(), v.decor().suffix()]));
+				comments.push(Some(array.trailing()));
+			}
+
+			for comment in comments.into_iter().flatten().filter_map(|c| c.as_str()) {
+				for found in directive.captures_iter(comment) {
+					let deps = found.get(1).map_or("", |deps| deps.as_str());
+					let deps = deps.split(',').map(str::trim).filter(|d| !d.is_empty());
+					let before = allow.len();
+
+					allow.extend(deps.map(|dep| Allow {
+						feature: feature.into(),
+						dependency: Some(dep.into()),
+					}));
+					if allow.len() == before {
+						allow.push(Allow { feature: feature.into(), dependency: None });
+					}
+				}
+			}
+		}
+
+		Ok(allow)
+	}
+
+	/// Whether findings about `dependency` in `feature` are silenced.
+	///
+	/// Findings without a dependency are only silenced by entries without one.
+	pub fn allows(&self, feature: &str, dependency: Option<&str>) -> bool {
+		self.allow.iter().any(|allow| {
+			allow.feature == feature &&
+				(allow.dependency.is_none() || allow.dependency.as_deref() == dependency)
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn from_toml_works() {
+		let config = Config::from_toml(
+			r#"
+features = ["std", "runtime-benchmarks"]
+exclude = ["node-cli"]
+lints = ["missing-propagation"]
+
+[dep-kinds]
+std = ["normal"]
+"#,
+		)
+		.unwrap();
+
+		assert_eq!(config.features, vec!["std", "runtime-benchmarks"]);
+		assert_eq!(config.exclude, vec!["node-cli"]);
+		assert!(config.is_enabled(FindingKind::MissingPropagation));
+		assert!(!config.is_enabled(FindingKind::MissingFeature));
+		assert_eq!(config.dep_kinds("std"), &[DepKind::Normal]);
+		assert_eq!(config.dep_kinds("runtime-benchmarks"), &DEFAULT_DEP_KINDS);
+	}
+
+	#[test]
+	fn from_metadata_works() {
+		let metadata = serde_json::json!({ "features": ["std"] });
+		let config = Config::from_metadata(metadata).unwrap();
+
+		assert_eq!(config.features, vec!["std"]);
+		assert_eq!(config.lints, Config::default().lints, "Lints are defaulted");
+	}
+
+	#[test]
+	fn unknown_fields_error() {
+		assert!(Config::from_toml("feature = [\"std\"]").is_err());
+	}
+
+	#[test]
+	fn directives_work() {
+		let allow = PackageConfig::directives(
+			r#"
+[features]
+default = ["std"] # feature: allow
+std = [
+	"frame-support/std",
+	# feature: allow(sp-io, sp-core)
+	"sp-runtime/std",
+	# Some other comment
+]
+runtime-benchmarks = [
+	"frame-support/runtime-benchmarks", # feature: allow(sp-runtime)
+]
+"#,
+		)
+		.unwrap();
+		let config = PackageConfig { allow };
+
+		assert!(config.allows("default", None));
+		assert!(config.allows("default", Some("sp-io")), "Allows all dependencies");
+		assert!(config.allows("std", Some("sp-io")));
+		assert!(config.allows("std", Some("sp-core")));
+		assert!(!config.allows("std", Some("sp-runtime")));
+		assert!(!config.allows("std", None), "Only allows the listed dependencies");
+		assert!(config.allows("runtime-benchmarks", Some("sp-runtime")));
+	}
+
+	#[test]
+	fn package_metadata_works() {
+		let metadata = serde_json::json!({
+			"allow": [{ "feature": "std", "dependency": "sp-io" }, { "feature": "try-runtime" }]
+		});
+		let config: PackageConfig = serde_json::from_value(metadata).unwrap();
+
+		assert!(config.allows("std", Some("sp-io")));
+		assert!(!config.allows("std", Some("sp-core")));
+		assert!(config.allows("try-runtime", Some("sp-core")));
+		assert!(!config.allows("runtime-benchmarks", Some("sp-io")));
+	}
+}
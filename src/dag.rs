@@ -10,22 +10,25 @@
 use core::fmt::{Display, Formatter};
 use std::{
 	borrow::{Cow, ToOwned},
-	collections::{BTreeMap, BTreeSet},
+	collections::{BTreeMap, BTreeSet, VecDeque},
 };
 
 /// Represents *Directed Acyclic Graph* through its edge relation.
 ///
-/// A "node" in that sense is anything on the left- or right-hand side of this relation.
+/// A "node" in that sense is anything on the left- or right-hand side of this relation. Edges can
+/// optionally carry labels of type `L`, eg. the kind of a dependency.
 #[derive(Clone)]
-pub struct Dag<T> {
+pub struct Dag<T, L = ()> {
 	/// Dependant -> Dependency
 	/// eg: Polkadot -> Substrate or Me -> Rust
 	pub edges: BTreeMap<T, BTreeSet<T>>,
+	/// Dependant -> Dependency -> Labels of that edge
+	pub labels: BTreeMap<T, BTreeMap<T, BTreeSet<L>>>,
 }
 
-impl<T> Default for Dag<T> {
+impl<T, L> Default for Dag<T, L> {
 	fn default() -> Self {
-		Self { edges: BTreeMap::new() }
+		Self { edges: BTreeMap::new(), labels: BTreeMap::new() }
 	}
 }
 
@@ -106,9 +109,103 @@ where
 	}
 }
 
-impl<T> Dag<T>
+/// A label of a [Dag] edge that restricts where in a path the edge can be used.
+pub trait EdgeLabel {
+	/// Whether an edge with only such labels can only be the first hop of a path.
+	///
+	/// Eg. cargo builds the dev-dependencies of a crate, but never those of its dependencies.
+	fn first_hop_only(&self) -> bool {
+		false
+	}
+}
+
+impl EdgeLabel for () {}
+
+/// Nodes and edges that a path through a [Dag] must not use.
+#[derive(Clone, Debug)]
+pub struct Avoid<'a, T> {
+	/// Nodes that must not be visited.
+	pub nodes: BTreeSet<&'a T>,
+	/// Edges (from, to) that must not be followed.
+	pub edges: BTreeSet<(&'a T, &'a T)>,
+}
+
+impl<T> Default for Avoid<'_, T> {
+	fn default() -> Self {
+		Self { nodes: BTreeSet::new(), edges: BTreeSet::new() }
+	}
+}
+
+impl<'a, T: Ord> Avoid<'a, T> {
+	/// Whether a path may follow the edge from `from` to `to`.
+	pub fn allows(&self, from: &'a T, to: &'a T) -> bool {
+		!self.nodes.contains(to) && !self.edges.contains(&(from, to))
+	}
+
+	/// Whether nothing is avoided.
+	pub fn is_empty(&self) -> bool {
+		self.nodes.is_empty() && self.edges.is_empty()
+	}
+}
+
+/// Iterator over all paths between two nodes of a [Dag].
+///
+/// Created by [Dag::all_paths].
+pub struct AllPaths<'a, T, L> {
+	dag: &'a Dag<T, L>,
+	to: &'a T,
+	avoid: Avoid<'a, T>,
+	/// The remaining dependencies of each node on the current path.
+	stack: Vec<std::vec::IntoIter<&'a T>>,
+	/// The current path.
+	path: Vec<&'a T>,
+	/// Whether `from` and `to` are the same and that path was not yet returned.
+	trivial: bool,
+}
+
+impl<'a, T, L> Iterator for AllPaths<'a, T, L>
+where
+	T: Ord + Clone,
+	L: Ord + Clone + EdgeLabel,
+{
+	type Item = Path<'a, T>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.trivial {
+			self.trivial = false;
+			self.stack.clear();
+			return self.path.clone().try_into().ok()
+		}
+
+		while let Some(deps) = self.stack.last_mut() {
+			let Some(dep) = deps.next() else {
+				self.stack.pop();
+				self.path.pop();
+				continue
+			};
+			let node = self.path.last().expect("Stack and path have the same length");
+			if self.path.contains(&dep) || !self.avoid.allows(node, dep) {
+				continue
+			}
+			if dep == self.to {
+				let mut path = self.path.clone();
+				path.push(dep);
+				return path.try_into().ok()
+			}
+			if self.dag.lhs_contains(dep) {
+				let start = self.path[0];
+				self.stack.push(self.dag.successors(dep, start).collect::<Vec<_>>().into_iter());
+				self.path.push(dep);
+			}
+		}
+		None
+	}
+}
+
+impl<T, L> Dag<T, L>
 where
 	T: Ord + PartialEq + Clone,
+	L: Ord + Clone,
 {
 	/// Create a new empty [Dag].
 	pub fn new() -> Self {
@@ -120,6 +217,24 @@ where
 		self.edges.entry(from).or_default().insert(to);
 	}
 
+	/// Connect two nodes and label the edge with `label`.
+	///
+	/// An edge can have multiple labels.
+	pub fn add_labeled_edge(&mut self, from: T, to: T, label: L) {
+		self.labels
+			.entry(from.clone())
+			.or_default()
+			.entry(to.clone())
+			.or_default()
+			.insert(label);
+		self.add_edge(from, to);
+	}
+
+	/// The labels of the edge from `from` to `to`.
+	pub fn labels<'a>(&'a self, from: &T, to: &T) -> impl Iterator<Item = &'a L> {
+		self.labels.get(from).and_then(|labels| labels.get(to)).into_iter().flatten()
+	}
+
 	/// Add a node to the Dag without any edges.
 	pub fn add_node(&mut self, node: T) {
 		self.edges.entry(node).or_default();
@@ -141,11 +256,31 @@ where
 
 	/// Whether `to` appears on the rhs of the edge relation.
 	///
-	/// Aka: Whether any other node depends on `self`.
+	/// Aka: Whether any other node depends on `self`. This scans all edges; use [Self::reversed]
+	/// for repeated queries.
 	pub fn rhs_contains(&self, to: &T) -> bool {
 		self.edges.values().any(|v| v.contains(to))
 	}
 
+	/// The `Dag` with all edges pointing in the opposite direction.
+	///
+	/// Aka: Dependency -> Dependant. Labels are kept and all nodes remain on the lhs.
+	pub fn reversed(&self) -> Self {
+		let mut reversed = Self::new();
+
+		for (from, tos) in self.edges.iter() {
+			reversed.add_node(from.clone());
+			for to in tos {
+				reversed.add_edge(to.clone(), from.clone());
+				for label in self.labels(from, to) {
+					reversed.add_labeled_edge(to.clone(), from.clone(), label.clone());
+				}
+			}
+		}
+
+		reversed
+	}
+
 	/// The `Dag` only containing the node `from` and its direct dependencies.
 	///
 	/// This can be inflated back to the original `Dag` by calling
@@ -153,8 +288,12 @@ where
 	pub fn dag_of(&self, from: T) -> Self {
 		let mut edges = BTreeMap::new();
 		let rhs = self.edges.get(&from).cloned().unwrap_or_default();
+		let mut labels = BTreeMap::new();
+		if let Some(l) = self.labels.get(&from) {
+			labels.insert(from.clone(), l.clone());
+		}
 		edges.insert(from, rhs);
-		Self { edges }
+		Self { edges, labels }
 	}
 
 	/// Get get a ref to the a LHS node.
@@ -163,6 +302,8 @@ where
 	}
 
 	/// Calculate the transitive hull of `self`.
+	///
+	/// The added edges have no labels.
 	pub fn transitive_hull(&mut self) {
 		let topology = self.clone();
 		self.transitive_in(&topology);
@@ -213,6 +354,97 @@ where
 		changed
 	}
 
+	/// The number of edges in the graph.
+	pub fn num_edges(&self) -> usize {
+		self.edges.values().map(|v| v.len()).sum()
+	}
+
+	/// The number of nodes in the graph.
+	pub fn num_nodes(&self) -> usize {
+		self.edges.len()
+	}
+}
+
+/// Path algorithms.
+///
+/// They all follow the edges of [Self::successors], so that paths respect the [EdgeLabel]s.
+impl<T, L> Dag<T, L>
+where
+	T: Ord + PartialEq + Clone,
+	L: Ord + Clone + EdgeLabel,
+{
+	/// The dependencies of `node` that a path which started at `start` can continue with.
+	///
+	/// Edges whose labels are all [EdgeLabel::first_hop_only] are only followed from `start`.
+	pub fn successors<'a>(&'a self, node: &'a T, start: &T) -> impl Iterator<Item = &'a T> + 'a {
+		let first = node == start;
+		self.edges
+			.get(node)
+			.into_iter()
+			.flatten()
+			.filter(move |to| first || self.followable(node, to))
+	}
+
+	/// Whether the edge from `from` to `to` can be used after the first hop of a path.
+	fn followable(&self, from: &T, to: &T) -> bool {
+		let mut labels = self.labels(from, to).peekable();
+		labels.peek().is_none() || labels.any(|label| !label.first_hop_only())
+	}
+
+	/// All nodes that are reachable from `from` within `depth` hops, together with their distance.
+	///
+	/// `from` itself is not included, unless it is part of a cycle. No `depth` means no limit.
+	pub fn reachable<'a>(&'a self, from: &'a T, depth: Option<usize>) -> BTreeMap<&'a T, usize> {
+		let mut reached = BTreeMap::new();
+		let mut queue = VecDeque::from([(from, 0)]);
+
+		while let Some((node, distance)) = queue.pop_front() {
+			if depth.is_some_and(|depth| distance >= depth) {
+				continue
+			}
+			for neighbor in self.successors(node, from) {
+				if !reached.contains_key(neighbor) {
+					reached.insert(neighbor, distance + 1);
+					queue.push_back((neighbor, distance + 1));
+				}
+			}
+		}
+
+		reached
+	}
+
+	/// All nodes from which `to` is reachable within `depth` hops, together with their distance.
+	///
+	/// The reverse of [Self::reachable]: a node that only reaches `to` through a
+	/// [EdgeLabel::first_hop_only] edge counts, but the nodes behind it do not.
+	pub fn dependants<'a>(&'a self, to: &'a T, depth: Option<usize>) -> BTreeMap<&'a T, usize> {
+		// Dependency -> Dependants
+		let mut reversed = BTreeMap::<&T, Vec<&T>>::new();
+		for (dependant, dependencies) in self.edges.iter() {
+			for dependency in dependencies {
+				reversed.entry(dependency).or_default().push(dependant);
+			}
+		}
+		let mut reached = BTreeMap::new();
+		// Nodes whose dependants are already queued.
+		let mut extended = BTreeSet::from([to]);
+		let mut queue = VecDeque::from([(to, 0)]);
+
+		while let Some((node, distance)) = queue.pop_front() {
+			if depth.is_some_and(|depth| distance >= depth) {
+				continue
+			}
+			for dependant in reversed.get(node).into_iter().flatten().copied() {
+				reached.entry(dependant).or_insert(distance + 1);
+				if self.followable(dependant, node) && extended.insert(dependant) {
+					queue.push_back((dependant, distance + 1));
+				}
+			}
+		}
+
+		reached
+	}
+
 	/// Find *any* path from `from` to `to`.
 	///
 	/// Note that 1) *the* shortest path does not necessarily exist and 2) this function does not
@@ -231,25 +463,224 @@ where
 			if node == to {
 				return path.try_into().ok()
 			}
-			if let Some(neighbors) = self.edges.get(node) {
-				for neighbor in neighbors.iter() {
-					path.push(neighbor);
-					stack.push((neighbor, path.clone()));
-					path.pop();
+			for neighbor in self.successors(node, from) {
+				path.push(neighbor);
+				stack.push((neighbor, path.clone()));
+				path.pop();
+			}
+		}
+		None
+	}
+
+	/// Find the shortest path from `from` to `to`.
+	///
+	/// Breadth-first search that visits the dependencies of a node in their order. From all
+	/// shortest paths, the one that branches off to the smallest node first is returned. This makes
+	/// the result deterministic.
+	///
+	/// This returns `Some` if (and only if) `to` is *reachable* from `from`.
+	pub fn shortest_path<'a>(&'a self, from: &'a T, to: &T) -> Option<Path<'a, T>> {
+		self.shortest_path_avoiding(from, to, &Avoid::default())
+	}
+
+	/// Find the shortest path from `from` to `to` that does not use anything in `avoid`.
+	///
+	/// Returns `None` if removing the avoided nodes and edges disconnects `to` from `from`.
+	pub fn shortest_path_avoiding<'a>(
+		&'a self,
+		from: &'a T,
+		to: &T,
+		avoid: &Avoid<'a, T>,
+	) -> Option<Path<'a, T>> {
+		self.shortest_nodes(from, from, to, avoid)?.try_into().ok()
+	}
+
+	/// Breadth-first search for [Self::shortest_path_avoiding].
+	///
+	/// Searches from `from` as part of a path that started at `start`.
+	fn shortest_nodes<'a>(
+		&'a self,
+		start: &T,
+		from: &'a T,
+		to: &T,
+		avoid: &Avoid<'a, T>,
+	) -> Option<Vec<&'a T>> {
+		// Node -> The node from which it was discovered.
+		let mut parents = BTreeMap::<&T, &T>::new();
+		let mut queue = VecDeque::from([from]);
+
+		while let Some(node) = queue.pop_front() {
+			if node == to {
+				let mut path = vec![node];
+				while let Some(parent) = parents.get(path.last().unwrap()) {
+					path.push(parent);
+				}
+				path.reverse();
+				return Some(path)
+			}
+			for neighbor in self.successors(node, start) {
+				if neighbor == from ||
+					parents.contains_key(neighbor) ||
+					!avoid.allows(node, neighbor)
+				{
+					continue
 				}
+				parents.insert(neighbor, node);
+				queue.push_back(neighbor);
 			}
 		}
 		None
 	}
 
-	/// The number of edges in the graph.
-	pub fn num_edges(&self) -> usize {
-		self.edges.values().map(|v| v.len()).sum()
+	/// Find the `k` shortest paths from `from` to `to`.
+	///
+	/// Uses Yen's algorithm. The paths are sorted by their length and contain no cycles. Paths of
+	/// the same length are sorted by their nodes.
+	pub fn k_shortest_paths<'a>(&'a self, from: &'a T, to: &T, k: usize) -> Vec<Path<'a, T>> {
+		self.k_shortest_paths_avoiding(from, to, k, &Avoid::default())
 	}
 
-	/// The number of nodes in the graph.
-	pub fn num_nodes(&self) -> usize {
-		self.edges.len()
+	/// Find the `k` shortest paths from `from` to `to` that do not use anything in `avoid`.
+	pub fn k_shortest_paths_avoiding<'a>(
+		&'a self,
+		from: &'a T,
+		to: &T,
+		k: usize,
+		avoid: &Avoid<'a, T>,
+	) -> Vec<Path<'a, T>> {
+		if k == 0 {
+			return Vec::new()
+		}
+		let Some(shortest) = self.shortest_nodes(from, from, to, avoid) else { return Vec::new() };
+		let mut found = vec![shortest];
+		// Sorted by length and then by nodes.
+		let mut candidates = BTreeSet::<(usize, Vec<&T>)>::new();
+
+		while found.len() < k {
+			let last = found.last().expect("Starts with one path");
+
+			// Deviate from the last path at every node except the target.
+			for i in 0..last.len() - 1 {
+				let (root, spur) = (&last[..i], last[i]);
+				let mut spur_avoid = avoid.clone();
+				// Do not find any of the known paths again.
+				spur_avoid.edges.extend(
+					found
+						.iter()
+						.filter(|path| path.len() > i + 1 && path[..=i] == last[..=i])
+						.map(|path| (path[i], path[i + 1])),
+				);
+				// Do not loop back into the root.
+				spur_avoid.nodes.extend(root);
+
+				if let Some(tail) = self.shortest_nodes(from, spur, to, &spur_avoid) {
+					let path = [root, &tail].concat();
+					if !found.contains(&path) {
+						candidates.insert((path.len(), path));
+					}
+				}
+			}
+
+			match candidates.pop_first() {
+				Some((_, path)) => found.push(path),
+				None => break,
+			}
+		}
+
+		found.into_iter().filter_map(|path| path.try_into().ok()).collect()
+	}
+
+	/// Iterate over all paths from `from` to `to` that contain no cycles.
+	///
+	/// The paths are found lazily through a depth-first search. Their number can grow
+	/// exponentially with the size of the graph.
+	pub fn all_paths<'a>(&'a self, from: &'a T, to: &'a T) -> AllPaths<'a, T, L> {
+		self.all_paths_avoiding(from, to, Avoid::default())
+	}
+
+	/// Iterate over all paths from `from` to `to` that do not use anything in `avoid`.
+	pub fn all_paths_avoiding<'a>(
+		&'a self,
+		from: &'a T,
+		to: &'a T,
+		avoid: Avoid<'a, T>,
+	) -> AllPaths<'a, T, L> {
+		let deps = self.successors(from, from).collect::<Vec<_>>();
+		let stack = if self.lhs_contains(from) { vec![deps.into_iter()] } else { Vec::new() };
+		AllPaths { dag: self, to, avoid, stack, path: vec![from], trivial: from == to }
+	}
+
+	/// The smallest set of edges whose removal makes `to` unreachable from `from`.
+	///
+	/// Computes a maximum flow with unit capacities (Edmonds-Karp). From all minimal cuts, the one
+	/// closest to `from` is returned. Returns an empty set if `to` is not reachable and `None` if
+	/// `from` and `to` are the same, since then no cut exists.
+	pub fn min_cut<'a>(&'a self, from: &'a T, to: &'a T) -> Option<BTreeSet<(&'a T, &'a T)>> {
+		if from == to {
+			return None
+		}
+		// All edges that a path from `from` can use.
+		let edges = self
+			.edges
+			.keys()
+			.flat_map(|dependant| self.successors(dependant, from).map(move |d| (dependant, d)))
+			.collect::<Vec<_>>();
+		// Dependency -> Dependants
+		let mut reversed = BTreeMap::<&T, BTreeSet<&T>>::new();
+		for (dependant, dependency) in edges.iter() {
+			reversed.entry(*dependency).or_default().insert(*dependant);
+		}
+		// The edges that carry flow.
+		let mut flow = BTreeSet::<(&T, &T)>::new();
+
+		// Augment the flow along shortest paths until `to` becomes unreachable in the residual
+		// graph.
+		let reached = loop {
+			// Node -> (Previous node, whether the edge was used in reverse)
+			let mut parents = BTreeMap::<&T, (&T, bool)>::new();
+			let mut queue = VecDeque::from([from]);
+
+			while let Some(node) = queue.pop_front() {
+				if node == to {
+					break
+				}
+				let forward = self.successors(node, from);
+				let forward = forward.filter(|next| !flow.contains(&(node, *next)));
+				let backward = reversed.get(node).into_iter().flatten().copied();
+				let backward = backward.filter(|prev| flow.contains(&(*prev, node)));
+
+				for (next, reverse) in
+					forward.map(|n| (n, false)).chain(backward.map(|n| (n, true)))
+				{
+					if next != from && !parents.contains_key(next) {
+						parents.insert(next, (node, reverse));
+						queue.push_back(next);
+					}
+				}
+			}
+			if !parents.contains_key(to) {
+				break parents
+			}
+
+			let mut node = to;
+			while node != from {
+				let (prev, reverse) = parents[node];
+				if reverse {
+					flow.remove(&(node, prev));
+				} else {
+					flow.insert((prev, node));
+				}
+				node = prev;
+			}
+		};
+
+		// All edges that leave the part that is still reachable from `from`.
+		let reached = |node: &T| node == from || reached.contains_key(node);
+		let cut = edges
+			.into_iter()
+			.filter(|(dependant, dependency)| reached(dependant) && !reached(dependency))
+			.collect();
+		Some(cut)
 	}
 }
 
@@ -279,4 +710,234 @@ mod tests {
 		let dag2 = dag.clone().into_transitive_hull();
 		assert_eq!(dag.num_edges(), dag2.num_edges());
 	}
+
+	#[rstest]
+	#[case(vec![("A", "B"), ("B", "C")], "C", Some(vec!["A", "B", "C"]))]
+	#[case(vec![("A", "B"), ("B", "C"), ("C", "D"), ("A", "D")], "D", Some(vec!["A", "D"]))]
+	#[case(vec![("A", "C"), ("C", "D"), ("A", "B"), ("B", "D")], "D", Some(vec!["A", "B", "D"]))]
+	#[case(vec![("A", "B"), ("C", "D")], "D", None)]
+	#[case(vec![("A", "B")], "A", Some(vec!["A"]))]
+	fn dag_shortest_path_works(
+		#[case] edges: Vec<(&str, &str)>,
+		#[case] to: &str,
+		#[case] expected: Option<Vec<&str>>,
+	) {
+		let mut dag = Dag::<&str>::default();
+		for (from, to) in edges {
+			dag.add_edge(from, to);
+		}
+		let path = dag.shortest_path(&"A", &to).map(|p| p.0.into_iter().map(|n| *n).collect());
+		assert_eq!(path, expected);
+	}
+
+	#[rstest]
+	#[case(0, vec![])]
+	#[case(1, vec![vec!["A", "E"]])]
+	#[case(3, vec![vec!["A", "E"], vec!["A", "B", "E"], vec!["A", "D", "E"]])]
+	#[case(5, vec![
+		vec!["A", "E"],
+		vec!["A", "B", "E"],
+		vec!["A", "D", "E"],
+		vec!["A", "B", "C", "E"],
+		vec!["A", "B", "D", "E"],
+	])]
+	#[case(9, vec![
+		vec!["A", "E"],
+		vec!["A", "B", "E"],
+		vec!["A", "D", "E"],
+		vec!["A", "B", "C", "E"],
+		vec!["A", "B", "D", "E"],
+		vec!["A", "B", "C", "D", "E"],
+	])]
+	fn dag_k_shortest_paths_work(#[case] k: usize, #[case] expected: Vec<Vec<&str>>) {
+		let dag = diamond();
+		let paths: Vec<_> = dag.k_shortest_paths(&"A", &"E", k).into_iter().map(nodes).collect();
+		assert_eq!(paths, expected);
+	}
+
+	#[test]
+	fn dag_all_paths_works() {
+		let mut dag = diamond();
+		// Cycles are not followed.
+		dag.add_edge("C", "B");
+		let mut paths: Vec<_> = dag.all_paths(&"A", &"E").map(nodes).collect();
+		paths.sort_by_key(|p| (p.len(), p.clone()));
+		let expected: Vec<_> =
+			dag.k_shortest_paths(&"A", &"E", usize::MAX).into_iter().map(nodes).collect();
+
+		assert_eq!(paths.len(), 6);
+		assert_eq!(paths, expected);
+		assert_eq!(dag.all_paths(&"A", &"A").map(nodes).collect::<Vec<_>>(), vec![vec!["A"]]);
+		assert_eq!(dag.all_paths(&"E", &"A").count(), 0);
+		assert_eq!(dag.all_paths(&"X", &"A").count(), 0);
+	}
+
+	fn nodes<'a>(path: Path<&'a str>) -> Vec<&'a str> {
+		path.0.into_iter().map(|n| *n).collect()
+	}
+
+	#[test]
+	fn dag_avoiding_works() {
+		let dag = diamond();
+		let mut avoid = Avoid::default();
+		avoid.edges.insert((&"A", &"E"));
+		avoid.nodes.insert(&"B");
+
+		let path = dag.shortest_path_avoiding(&"A", &"E", &avoid).map(nodes);
+		assert_eq!(path, Some(vec!["A", "D", "E"]));
+		let paths: Vec<_> = dag
+			.k_shortest_paths_avoiding(&"A", &"E", 9, &avoid)
+			.into_iter()
+			.map(nodes)
+			.collect();
+		assert_eq!(paths, vec![vec!["A", "D", "E"]]);
+		let paths: Vec<_> = dag.all_paths_avoiding(&"A", &"E", avoid.clone()).map(nodes).collect();
+		assert_eq!(paths, vec![vec!["A", "D", "E"]]);
+
+		avoid.nodes.insert(&"D");
+		assert!(dag.shortest_path_avoiding(&"A", &"E", &avoid).is_none(), "Disconnected");
+		assert_eq!(dag.all_paths_avoiding(&"A", &"E", avoid).count(), 0);
+	}
+
+	#[rstest]
+	#[case(vec![("A", "B"), ("B", "C")], "C", Some(vec![("A", "B")]))]
+	#[case(vec![("A", "B"), ("B", "C"), ("A", "C")], "C", Some(vec![("A", "B"), ("A", "C")]))]
+	#[case(vec![("A", "B"), ("A", "C"), ("B", "D"), ("C", "D"), ("D", "E")], "E", Some(vec![("D", "E")]))]
+	#[case(vec![("A", "B"), ("B", "A"), ("B", "C")], "C", Some(vec![("A", "B")]))]
+	#[case(vec![("A", "B"), ("C", "D")], "D", Some(vec![]))]
+	#[case(vec![("A", "B")], "A", None)]
+	fn dag_min_cut_works(
+		#[case] edges: Vec<(&str, &str)>,
+		#[case] to: &str,
+		#[case] expected: Option<Vec<(&str, &str)>>,
+	) {
+		let mut dag = Dag::<&str>::default();
+		for (from, to) in edges {
+			dag.add_edge(from, to);
+		}
+		let cut = dag
+			.min_cut(&"A", &to)
+			.map(|cut| cut.into_iter().map(|(f, t)| (*f, *t)).collect());
+		assert_eq!(cut, expected);
+	}
+
+	#[test]
+	fn dag_min_cut_disconnects() {
+		let dag = diamond();
+		let cut = dag.min_cut(&"A", &"E").unwrap();
+		assert_eq!(cut.len(), 3);
+
+		let avoid = Avoid { nodes: BTreeSet::new(), edges: cut };
+		assert!(dag.shortest_path_avoiding(&"A", &"E", &avoid).is_none());
+	}
+
+	/// A -> B -> C -> D -> E with shortcuts from A, B and C.
+	fn diamond() -> Dag<&'static str> {
+		let mut dag = Dag::default();
+		for (from, to) in [
+			("A", "B"),
+			("A", "D"),
+			("A", "E"),
+			("B", "C"),
+			("B", "D"),
+			("B", "E"),
+			("C", "D"),
+			("C", "E"),
+			("D", "E"),
+		] {
+			dag.add_edge(from, to);
+		}
+		dag
+	}
+
+	#[test]
+	fn dag_reversed_works() {
+		let mut dag = Dag::<&str, &str>::default();
+		dag.add_labeled_edge("A", "B", "normal");
+		dag.add_edge("B", "C");
+		let reversed = dag.reversed();
+
+		assert!(reversed.connected(&"B", &"A"));
+		assert!(reversed.connected(&"C", &"B"));
+		assert!(!reversed.connected(&"A", &"B"));
+		assert!(reversed.lhs_contains(&"A"));
+		assert_eq!(reversed.labels(&"B", &"A").collect::<Vec<_>>(), vec![&"normal"]);
+		assert_eq!(reversed.num_edges(), dag.num_edges());
+	}
+
+	#[rstest]
+	#[case(Some(1), vec![("B", 1), ("D", 1), ("E", 1)])]
+	#[case(Some(2), vec![("B", 1), ("C", 2), ("D", 1), ("E", 1)])]
+	#[case(None, vec![("B", 1), ("C", 2), ("D", 1), ("E", 1)])]
+	#[case(Some(0), vec![])]
+	fn dag_reachable_works(#[case] depth: Option<usize>, #[case] expected: Vec<(&str, usize)>) {
+		let dag = diamond();
+		let reached = dag.reachable(&"A", depth);
+		assert_eq!(reached.into_iter().map(|(n, d)| (*n, d)).collect::<Vec<_>>(), expected);
+		assert_eq!(dag.reversed().reachable(&"C", None).len(), 2);
+	}
+
+	#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+	enum Kind {
+		Normal,
+		Dev,
+	}
+
+	impl EdgeLabel for Kind {
+		fn first_hop_only(&self) -> bool {
+			*self == Self::Dev
+		}
+	}
+
+	/// A -> B -> C -> D where B only dev-depends on C, and A also dev-depends on D.
+	fn dev() -> Dag<&'static str, Kind> {
+		let mut dag = Dag::default();
+		dag.add_labeled_edge("A", "B", Kind::Normal);
+		dag.add_labeled_edge("B", "C", Kind::Dev);
+		dag.add_labeled_edge("C", "D", Kind::Normal);
+		dag.add_labeled_edge("A", "D", Kind::Dev);
+		dag
+	}
+
+	#[test]
+	fn dag_dev_edges_are_first_hops_only() {
+		let mut dag = dev();
+		assert_eq!(dag.successors(&"B", &"A").count(), 0);
+		assert_eq!(dag.successors(&"B", &"B").collect::<Vec<_>>(), vec![&"C"]);
+		assert!(dag.shortest_path(&"A", &"C").is_none());
+		assert!(dag.any_path(&"A", &"C").is_none());
+		assert_eq!(dag.all_paths(&"A", &"C").count(), 0);
+		assert!(dag.k_shortest_paths(&"A", &"C", 3).is_empty());
+		assert_eq!(dag.min_cut(&"A", &"C"), Some(BTreeSet::new()));
+		assert_eq!(dag.reachable(&"A", None).into_keys().collect::<Vec<_>>(), vec![&"B", &"D"]);
+		assert_eq!(dag.dependants(&"C", None).into_iter().collect::<Vec<_>>(), vec![(&"B", 1)]);
+		assert_eq!(dag.dependants(&"D", None).len(), 3, "A, B and C");
+		assert_eq!(dag.dependants(&"D", Some(1)).len(), 2, "A and C");
+		assert_eq!(dag.shortest_path(&"B", &"D").map(nodes), Some(vec!["B", "C", "D"]));
+
+		// The dev-dependency of `A` itself is followed.
+		assert_eq!(dag.shortest_path(&"A", &"D").map(nodes), Some(vec!["A", "D"]));
+		assert_eq!(dag.all_paths(&"A", &"D").map(nodes).collect::<Vec<_>>(), vec![vec!["A", "D"]]);
+		assert_eq!(dag.min_cut(&"A", &"D").unwrap().len(), 1);
+
+		// Also being a normal dependency lifts the restriction.
+		dag.add_labeled_edge("B", "C", Kind::Normal);
+		assert_eq!(dag.shortest_path(&"A", &"C").map(nodes), Some(vec!["A", "B", "C"]));
+		assert_eq!(dag.k_shortest_paths(&"A", &"D", 3).len(), 2);
+	}
+
+	#[test]
+	fn dag_labels_work() {
+		let mut dag = Dag::<&str, &str>::default();
+		dag.add_labeled_edge("A", "B", "normal");
+		dag.add_labeled_edge("A", "B", "dev");
+		dag.add_labeled_edge("B", "C", "build");
+		dag.add_edge("C", "D");
+
+		assert!(dag.connected(&"A", &"B"));
+		assert_eq!(dag.labels(&"A", &"B").collect::<Vec<_>>(), vec![&"dev", &"normal"]);
+		assert_eq!(dag.labels(&"C", &"D").count(), 0);
+		assert_eq!(dag.dag_of("B").labels(&"B", &"C").collect::<Vec<_>>(), vec![&"build"]);
+		assert_eq!(dag.dag_of("B").labels(&"A", &"B").count(), 0);
+	}
 }
@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// SPDX-FileCopyrightText: Oliver Tale-Yazdi <oliver@tasty.limo>
+
+//! Query the local git repository of a workspace.
+//!
+//! Only the local repository is read; nothing is fetched.
+
+use std::{
+	path::{Path, PathBuf},
+	process::Command,
+};
+
+/// Absolute paths of all manifests that changed between `rev` and the working tree of the
+/// repository that contains `dir`.
+///
+/// Deleted manifests are included as well, even though they do not exist anymore. Untracked
+/// manifests count as changed unless they are ignored.
+pub fn changed_manifests(dir: &Path, rev: &str) -> Result<Vec<PathBuf>, String> {
+	let root = git(dir, &["rev-parse", "--show-toplevel"])?;
+	let root = Path::new(root.trim());
+	let changed = git(root, &["diff", "--name-only", rev, "--"])?;
+	let untracked = git(root, &["ls-files", "--others", "--exclude-standard"])?;
+
+	Ok(changed
+		.lines()
+		.chain(untracked.lines())
+		.map(Path::new)
+		.filter(|path| path.file_name().is_some_and(|name| name == "Cargo.toml"))
+		.map(|path| root.join(path))
+		.collect())
+}
+
+/// Run git with `args` in `dir` and return its stdout.
+fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+	let output = Command::new("git")
+		.arg("-C")
+		.arg(dir)
+		.args(args)
+		.output()
+		.map_err(|e| format!("Failed to run git: {e}"))?;
+
+	if !output.status.success() {
+		return Err(format!(
+			"Failed to run git {}: {}",
+			args.join(" "),
+			String::from_utf8_lossy(&output.stderr).trim()
+		))
+	}
+	String::from_utf8(output.stdout).map_err(|e| format!("Failed to read git output: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn changed_manifests_works() {
+		let dir = tempfile::tempdir().unwrap();
+		let root = dir.path().canonicalize().unwrap();
+		let commit = |msg: &str| {
+			git(&root, &["add", "-A"]).unwrap();
+			git(&root, &["-c", "user.name=a", "-c", "user.email=a@b", "commit", "-qm", msg])
+				.unwrap();
+		};
+		git(&root, &["init", "-q"]).unwrap();
+		std::fs::create_dir_all(root.join("a/src")).unwrap();
+		std::fs::write(root.join("Cargo.toml"), "[workspace]").unwrap();
+		std::fs::write(root.join("a/Cargo.toml"), "[package]").unwrap();
+		std::fs::write(root.join("a/src/lib.rs"), "").unwrap();
+		commit("init");
+
+		std::fs::write(root.join("a/Cargo.toml"), "[package]\nname = \"a\"").unwrap();
+		std::fs::write(root.join("a/src/lib.rs"), "fn main() {}").unwrap();
+		assert_eq!(
+			changed_manifests(&root.join("a"), "HEAD").unwrap(),
+			vec![root.join("a/Cargo.toml")]
+		);
+
+		commit("change");
+		assert!(changed_manifests(&root, "HEAD").unwrap().is_empty());
+		assert_eq!(changed_manifests(&root, "HEAD~1").unwrap(), vec![root.join("a/Cargo.toml")]);
+		assert!(changed_manifests(&root, "unknown").is_err());
+
+		std::fs::create_dir_all(root.join("b")).unwrap();
+		std::fs::create_dir_all(root.join("ignored")).unwrap();
+		std::fs::write(root.join("b/Cargo.toml"), "[package]").unwrap();
+		std::fs::write(root.join("ignored/Cargo.toml"), "[package]").unwrap();
+		std::fs::write(root.join(".gitignore"), "ignored/").unwrap();
+		assert_eq!(
+			changed_manifests(&root.join("a"), "HEAD").unwrap(),
+			vec![root.join("b/Cargo.toml")],
+			"Untracked manifests changed"
+		);
+	}
+}
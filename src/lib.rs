@@ -2,8 +2,8 @@
 // SPDX-FileCopyrightText: Oliver Tale-Yazdi <oliver@tasty.limo>
 
 //! Understand why and how features are enabled in a rust workspace. `Feature` is able to
-//! automatically fix missing feature propagation to dependencies. Eventually it will be ready for
-//! CI use to check an MR for consistent feature usage.
+//! automatically fix missing feature propagation to dependencies. It can be used in CI to check an
+//! MR for consistent feature usage.
 //!
 //! ## Install
 //!
@@ -25,6 +25,7 @@
 //!
 //! ```pre
 //! Analyzing workspace
+//! Using all features
 //! crate "frame-support"
 //!   feature "runtime-benchmarks"
 //!     must propagate to:
@@ -36,6 +37,9 @@
 //!
 //! Without the `-p` it will detect many more problems. You can verify this for the [frame-support](https://github.com/paritytech/substrate/blob/ce2cee35f8f0fc5968ea6ffaffa6660dcd008804/frame/support/Cargo.toml#L71) which is indeed missing the feature for `sp-runtime` while that is clearly [sp-runtime](https://github.com/paritytech/substrate/blob/0b6aec52a90870c999856cd37f7d04789cdd8dfc/primitives/runtime/Cargo.toml#L43) it 🤔.
 //!
+//! Multiple features can be checked at once with a single `cargo metadata` invocation by passing
+//! them comma separated like `--feature std,runtime-benchmarks,try-runtime`.
+//!
 //! This can be fixed by applying the `--fix` flag like:  
 //!
 //! ```bash
@@ -53,9 +57,78 @@
 //! +]
 //! ```
 //!
-//! The auto-fix is currently a bit coarse, and does not check for optional dependencies. It will
-//! also not add the feature to crates that do not have it, but need it because of a dependency.
-//! This will be fixed soon.
+//! Optional dependencies are fixed with the weak `dep?/feature` syntax to not accidentally enable
+//! them. Crates that do not have the feature, but need it because of a dependency, get it added as
+//! well. This is repeated until nothing changes anymore, so that a chain of crates that all lack
+//! the feature is fixed in one run.
+//!
+//! ## Configuration
+//!
+//! The lint settings can be kept in the repository, so that CI and developers run the exact same
+//! checks. `feature lint propagate-feature` picks them up automatically from a `feature.toml` in
+//! the workspace root or from the `[workspace.metadata.feature]` table of the root manifest. See
+//! the [config] module for the available options.
+//!
+//! A dependency that is used with its default features may already have the feature enabled
+//! through its `default` feature. Such cases are reported as `implicit-default` warnings instead of
+//! missing propagation. Pass `--deny warnings` to require `default-features = false` plus explicit
+//! forwarding instead.
+//!
+//! Features are propagated to normal and dev dependencies by default. Build dependencies are
+//! compiled for the host and do not share features with the crate. This can be configured per
+//! feature in the `dep-kinds` table of the config.
+//!
+//! The `unused` lint is disabled by default. It warns about features that enable nothing, are not
+//! checked with `cfg(feature = ..)` or `CARGO_FEATURE_..` anywhere in the crate and are not
+//! enabled by any dependant.
+//!
+//! ## Suppressing findings
+//!
+//! Sometimes a feature is intentionally not propagated. Such findings can be silenced per crate in
+//! its `[package.metadata.feature]` table or with a `# feature: allow(dep)` comment inside of the
+//! feature. Silenced findings are not fixed and only show up as a "suppressed" count in the
+//! summary.
+//!
+//! ## CI usage
+//!
+//! The lint commands exit with `0` when everything is fine, `1` when there are findings and `2` on
+//! internal failures. Findings that were fixed with `--fix` do not count. Warnings only fail the
+//! run when `--deny warnings` is passed.
+//!
+//! Pass `--format json` to get one machine-readable record per finding instead of the
+//! human-readable output. The records are listed under `findings` next to the
+//! `feature_selection` that the analysis is based on. With `--format sarif` the findings are emitted as a [SARIF](https://sarifweb.azurewebsites.net/)
+//! log that points to the offending `Cargo.toml` lines, which can be uploaded to code-scanning UIs.
+//!
+//! Existing findings can be recorded with `--write-baseline baseline.json`. Passing
+//! `--baseline baseline.json` in later runs reports the recorded findings as known, so that only
+//! new findings fail the run. See the [baseline] module.
+//!
+//! For merge requests, `--changed-since <rev>` only checks the crates whose manifest changed since
+//! the given git revision and their direct dependants. Untracked manifests count as changed, and a
+//! change of the workspace manifest checks all members. Only the local repository is read.
+//!
+//! ## Example - Workspace rules
+//!
+//! Workspace-specific invariants can be written down in a spec file and checked with:
+//!
+//! ```bash
+//! feature lint rules --manifest-path ../substrate/Cargo.toml --spec spec.toml
+//! ```
+//!
+//! Each `test:` selects crates in `given`, asserts something about them in `then` and reports the
+//! `error` of `otherwise` for every violation. The `auto-fix` actions of a violated rule are
+//! applied with `--fix`. See the [rules] module for the syntax.
+//!
+//! ## Example - Forbidden dependencies
+//!
+//! Layering rules like "primitives must not depend on FRAME or pallets" can be checked directly:
+//!
+//! ```bash
+//! feature lint forbid-dependency --manifest-path ../substrate/Cargo.toml --from '^sp-' --to '^(frame|pallet)-' --transitive
+//! ```
+//!
+//! Every violating pair is printed together with a dependency path that shows how it comes to be.
 //!
 //! ## Example - Dependency tracing
 //!
@@ -74,16 +147,60 @@
 //! ```
 //!
 //! So it comes from libp2p, okay. Good to know.
+//!
+//! All commands accept `--dep-kinds normal,build,dev` to only consider some kinds of dependencies.
+//! `trace` reports the shortest path, or any path with `--any`. `--limit <N>` reports the `N`
+//! shortest paths and `--all` every path; `--collapse` shortens paths that end like an earlier one.
+//! `--avoid <crate>` and `--avoid-edge <from>:<to>` only consider paths that do not use the given
+//! crates or dependencies. This reports whether dropping them disconnects the two crates.
+//! `--cut` reports the fewest dependencies whose removal disconnects the two crates, each with the
+//! manifest that declares it.
+//! Pass `--show-kind` to see the kind of each hop. Dev-dependencies are only followed from the
+//! first crate of a path, since cargo never builds the dev-dependencies of a dependency.
+//!
+//! The dependencies are resolved with all features enabled by default, so that every optional
+//! dependency shows up. Pass `--features`, `--all-features` and `--no-default-features` like for
+//! cargo to analyze a specific build instead; `--features default` corresponds to a plain
+//! `cargo build`. Every output states which feature selection it is based on.
+//!
+//! The output of `cargo metadata --format-version 1` can be saved once and passed to any command
+//! with `--metadata-file`, or `--metadata-file -` to read it from stdin.
+//!
+//! Otherwise its output is cached in `target/feature-cache` and reused as long as no manifest of
+//! the workspace, its `Cargo.lock`, the crates that its member globs match or the passed options
+//! changed. Pass `--no-cache` to always run `cargo metadata`.
+//!
+//! With `--target <triple>` only the dependencies that are used when building for that target are
+//! considered, eg. `--target wasm32-unknown-unknown` for a `no_std` runtime build.
+//!
+//! ## Example - Reverse dependencies
+//!
+//! Which of our crates are affected by bumping `snow`?
+//!
+//! ```bash
+//! feature rdeps --manifest-path substrate/Cargo.toml snow --transitive
+//! ```
+//!
+//! This lists every workspace crate that depends on `snow`, together with the number of hops to
+//! it. Without `--transitive` only direct dependants are listed; `--depth <N>` limits the number
+//! of hops. A crate whose dependency only dev-depends on `snow` is not listed, since it never
+//! builds it.
 
 #![allow(dead_code)]
 
 pub mod autofix;
+pub mod baseline;
+pub mod cache;
 pub mod cmd;
+pub mod config;
 pub mod dag;
+pub mod git;
+pub mod rules;
+pub mod sarif;
 
 pub mod prelude {
 	pub use super::{
-		dag::{Dag, Path},
+		dag::{Dag, EdgeLabel, Path},
 		CrateId,
 	};
 }
@@ -4,12 +4,23 @@
 //! Entry point of the program.
 
 use clap::Parser;
-use feature::cmd::Command;
+use feature::cmd::{Command, Status};
+use std::process::ExitCode;
 
-fn main() {
+fn main() -> ExitCode {
 	let cmd = Command::parse();
 	env_logger::init_from_env(
 		env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "debug"),
 	);
-	cmd.run();
+
+	// Panics are internal failures as well and should not be confused with lint findings.
+	let status = match std::panic::catch_unwind(|| cmd.run()) {
+		Ok(Ok(status)) => status,
+		Ok(Err(err)) => {
+			log::error!("{err}");
+			Status::Failure
+		},
+		Err(_) => Status::Failure,
+	};
+	status.into()
 }
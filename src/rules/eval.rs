@@ -0,0 +1,281 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// SPDX-FileCopyrightText: Oliver Tale-Yazdi <oliver@tasty.limo>
+
+//! Evaluate a [Spec] against the dependency graph of a workspace.
+
+use super::*;
+use crate::{
+	cmd::{build_dag, dependency_key, resolve_dep, CrateDag},
+	CrateId,
+};
+use cargo_metadata::{Metadata, Package};
+use std::{
+	cell::{OnceCell, RefCell},
+	collections::{BTreeMap, BTreeSet},
+};
+
+/// Maps crate variables to the crates that they are bound to.
+pub type Binding = BTreeMap<String, CrateId>;
+
+/// A feature of a crate.
+type CrateFeature = (CrateId, String);
+
+/// Evaluates [Test]s against cargo metadata.
+pub struct Evaluator<'a> {
+	meta: &'a Metadata,
+	dag: CrateDag,
+	/// Lazily calculated transitive hull of `dag`.
+	hull: OnceCell<CrateDag>,
+	index: BTreeMap<CrateId, Package>,
+	/// Cache of all features that are transitively enabled by some features.
+	closures: RefCell<BTreeMap<Vec<CrateFeature>, BTreeSet<CrateFeature>>>,
+}
+
+impl<'a> Evaluator<'a> {
+	pub fn new(meta: &'a Metadata) -> Result<Self, String> {
+		let (dag, index) = build_dag(meta)?;
+		Ok(Self { meta, dag, hull: OnceCell::new(), index, closures: Default::default() })
+	}
+
+	/// The crate with id `id`.
+	pub fn krate(&self, id: &str) -> &Package {
+		self.index
+			.get(id)
+			.unwrap_or_else(|| panic!("Could not find crate {id} in the metadata"))
+	}
+
+	/// All bindings of the crate variables that satisfy `given` but violate `then`.
+	pub fn violations(&self, test: &Test) -> Vec<Binding> {
+		let mut violations = Vec::new();
+		self.bind(test, &mut Binding::new(), &mut violations);
+		violations
+	}
+
+	/// Bind the next unbound crate variable to all possible crates.
+	fn bind(&self, test: &Test, binding: &mut Binding, violations: &mut Vec<Binding>) {
+		let Some((var, selector)) = test.given.crates.get(binding.len()) else {
+			if !self.holds(&test.then, test, binding) {
+				violations.push(binding.clone());
+			}
+			return
+		};
+
+		for id in self.candidates(test, var, binding) {
+			if binding.values().any(|bound| bound == id) || !selector.matches(&self.krate(id).name)
+			{
+				continue
+			}
+			binding.insert(var.clone(), id.clone());
+			if self.given_holds(test, binding) {
+				self.bind(test, binding, violations);
+			}
+			binding.remove(var);
+		}
+	}
+
+	/// The crates that `var` could be bound to.
+	///
+	/// Uses the given dependencies of already bound variables to narrow down the search.
+	fn candidates<'b>(
+		&'b self,
+		test: &Test,
+		var: &str,
+		binding: &Binding,
+	) -> Box<dyn Iterator<Item = &'b CrateId> + 'b> {
+		for dep in test.given.dependencies.iter().filter(|d| d.to == var) {
+			if let Some(from) = binding.get(&dep.from) {
+				let dag = if dep.transitive { self.hull() } else { &self.dag };
+				return match dag.edges.get(from) {
+					Some(deps) => Box::new(deps.iter()),
+					None => Box::new(std::iter::empty()),
+				}
+			}
+		}
+
+		Box::new(self.index.keys())
+	}
+
+	/// Whether all given facts hold that only talk about bound variables.
+	fn given_holds(&self, test: &Test, binding: &Binding) -> bool {
+		let deps = test
+			.given
+			.dependencies
+			.iter()
+			.filter(|d| binding.contains_key(&d.from) && binding.contains_key(&d.to));
+		let features = test.given.features.iter().filter(|f| binding.contains_key(&f.krate));
+
+		deps.into_iter().all(|d| self.dependency_holds(d, binding)) &&
+			features
+				.into_iter()
+				.all(|f| self.krate(&binding[&f.krate]).features.contains_key(&f.feature))
+	}
+
+	/// Whether `assertion` holds for a complete `binding`.
+	fn holds(&self, assertion: &Assertion, test: &Test, binding: &Binding) -> bool {
+		match assertion {
+			Assertion::All(all) => all.iter().all(|a| self.holds(a, test, binding)),
+			Assertion::Not(inner) => !self.holds(inner, test, binding),
+			Assertion::Dependency(dep) => self.dependency_holds(dep, binding),
+			Assertion::Feature(feature) => {
+				let id = &binding[&feature.krate];
+				if !feature.enabled {
+					return self.krate(id).features.contains_key(&feature.feature)
+				}
+				let seeds = test
+					.given
+					.features
+					.iter()
+					.filter(|f| f.enabled)
+					.map(|f| (binding[&f.krate].clone(), f.feature.clone()))
+					.collect::<Vec<_>>();
+				self.enabled(seeds).contains(&(id.clone(), feature.feature.clone()))
+			},
+		}
+	}
+
+	fn dependency_holds(&self, dep: &DependencyFact, binding: &Binding) -> bool {
+		let (from, to) = (&binding[&dep.from], &binding[&dep.to]);
+
+		if dep.transitive {
+			self.hull().connected(from, to)
+		} else {
+			self.dag.connected(from, to)
+		}
+	}
+
+	/// Connects every crate with all of its transitive dependencies.
+	///
+	/// Dev-dependencies only count for the crate itself, not for its dependants.
+	fn hull(&self) -> &CrateDag {
+		self.hull.get_or_init(|| {
+			let mut hull = CrateDag::new();
+			for id in self.dag.edges.keys() {
+				hull.add_node(id.clone());
+				for (dep, _) in self.dag.reachable(id, None) {
+					hull.add_edge(id.clone(), dep.clone());
+				}
+			}
+			hull
+		})
+	}
+
+	/// All features that are transitively enabled by enabling `seeds`.
+	fn enabled(&self, seeds: Vec<CrateFeature>) -> BTreeSet<CrateFeature> {
+		if let Some(enabled) = self.closures.borrow().get(&seeds) {
+			return enabled.clone()
+		}
+		let mut enabled = BTreeSet::new();
+		let mut todo = seeds.clone();
+
+		while let Some((id, feature)) = todo.pop() {
+			if !enabled.insert((id.clone(), feature.clone())) {
+				continue
+			}
+			let pkg = self.krate(&id);
+			for entry in pkg.features.get(&feature).into_iter().flatten() {
+				if entry.starts_with("dep:") {
+					continue
+				}
+				let Some((dep, feature)) = entry.split_once('/') else {
+					todo.push((id.clone(), entry.clone()));
+					continue
+				};
+				let dep = dep.trim_end_matches('?');
+				let resolved = pkg
+					.dependencies
+					.iter()
+					.filter(|d| dependency_key(d) == dep)
+					.find_map(|d| resolve_dep(pkg, d, self.meta));
+				if let Some(resolved) = resolved {
+					todo.push((resolved.id.to_string(), feature.into()));
+				}
+			}
+		}
+
+		self.closures.borrow_mut().insert(seeds, enabled.clone());
+		enabled
+	}
+
+	/// The name under which `pkg` refers to the dependency `dep` in its manifest.
+	///
+	/// Falls back to the package name of `dep` if it is not a direct dependency.
+	pub fn dependency_key(&self, pkg: &str, dep: &str) -> String {
+		let pkg = self.krate(pkg);
+		pkg.dependencies
+			.iter()
+			.find(|d| resolve_dep(pkg, d, self.meta).is_some_and(|r| r.id.repr == dep))
+			.map(|d| dependency_key(d).to_string())
+			.unwrap_or_else(|| self.krate(dep).name.clone())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::rules::parse::parse;
+
+	/// Metadata of the `renamed` fixture where the `std` feature of `root` enables `std`.
+	///
+	/// `root` depends on `dep-a` as `codec` and on `dep-c` as `tgt`.
+	fn metadata(std: &[&str]) -> Metadata {
+		let raw = include_str!("../../tests/fixtures/renamed.json");
+		let mut meta: Metadata = serde_json::from_str(raw).unwrap();
+		let root = meta.packages.iter_mut().find(|p| p.name == "root").unwrap();
+		root.features.insert("std".into(), std.iter().map(|s| s.to_string()).collect());
+		root.features.insert("extra".into(), vec!["tgt/std".into()]);
+		meta
+	}
+
+	/// The crate names of all violating bindings of the only test in `spec`.
+	fn violations(meta: &Metadata, spec: &str) -> Vec<Vec<String>> {
+		let spec = parse(spec).unwrap();
+		let eval = Evaluator::new(meta).unwrap();
+		eval.violations(&spec.tests[0])
+			.into_iter()
+			.map(|b| b.values().map(|id| eval.krate(id).name.clone()).collect())
+			.collect()
+	}
+
+	#[test]
+	fn violations_enumerate_bindings() {
+		let spec = "test: t\n\tgiven:\n\t\tcrates: [A, B]\n\t\tdependencies:\n\t\t\tA: direct: B\n\tthen:\n\t\tnot: dependencies:\n\t\t\tA: direct: B\n\totherwise:\n\t\terror: \"e\"\n";
+		assert_eq!(
+			violations(&metadata(&[]), spec),
+			vec![vec!["root", "dep-a"], vec!["root", "dep-c"]]
+		);
+	}
+
+	#[test]
+	fn violations_respect_selectors() {
+		let spec = "test: t\n\tgiven:\n\t\tcrates:\n\t\t\tA: name: \"root\"\n\t\t\tB: name: regex(\"-c$\")\n\tthen:\n\t\tnot: dependencies:\n\t\t\tA: transitive: B\n\totherwise:\n\t\terror: \"e\"\n";
+		assert_eq!(violations(&metadata(&[]), spec), vec![vec!["root", "dep-c"]]);
+	}
+
+	#[rstest::rstest]
+	#[case(&[], &["dep-a", "dep-c"])]
+	#[case(&["codec/std"], &["dep-c"])]
+	#[case(&["codec?/std", "tgt/std"], &[])]
+	#[case(&["extra"], &["dep-a"])]
+	#[case(&["dep:codec"], &["dep-a", "dep-c"])]
+	fn enabled_follows_features(#[case] std: &[&str], #[case] missing: &[&str]) {
+		let spec = "test: t\n\tgiven:\n\t\tcrates: [A, B]\n\t\tdependencies:\n\t\t\tA: direct: B\n\t\tfeatures:\n\t\t\tA: enabled: std\n\t\t\tB: defines: std\n\tthen:\n\t\tB: enabled: std\n\totherwise:\n\t\terror: \"e\"\n";
+		let expected = missing.iter().map(|m| vec!["root", m]).collect::<Vec<_>>();
+		assert_eq!(violations(&metadata(std), spec), expected);
+	}
+
+	#[test]
+	fn dependency_key_works() {
+		let meta = metadata(&[]);
+		let eval = Evaluator::new(&meta).unwrap();
+		let id =
+			|name: &str| meta.packages.iter().find(|p| p.name == name).unwrap().id.repr.clone();
+
+		assert_eq!(eval.dependency_key(&id("root"), &id("dep-a")), "codec");
+		assert_eq!(eval.dependency_key(&id("root"), &id("dep-c")), "tgt");
+		assert_eq!(
+			eval.dependency_key(&id("dep-a"), &id("dep-c")),
+			"dep-c",
+			"Falls back to the name"
+		);
+	}
+}
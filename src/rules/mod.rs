@@ -0,0 +1,158 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// SPDX-FileCopyrightText: Oliver Tale-Yazdi <oliver@tasty.limo>
+
+//! A small rule language to express workspace-specific invariants.
+//!
+//! A spec file consists of `test:` blocks. Each block binds crate variables in `given`, asserts
+//! something about them in `then` and describes the error and possible auto-fixes in
+//! `otherwise`. Nested blocks are indented:
+//!
+//! ```pre
+//! test: Check that the feature propagates.
+//!     given:
+//!         crates: [A, B]
+//!         dependencies:
+//!             A: direct: B
+//!         features:
+//!             A: enabled: runtime-benchmarks
+//!             B: defines: runtime-benchmarks
+//!     then:
+//!         B: enabled: runtime-benchmarks
+//!     otherwise:
+//!         error: "feature `runtime-benchmarks` is not propagated"
+//!             auto-fix: enable-feature: A: runtime-benchmarks: B/runtime-benchmarks
+//! ```
+//!
+//! Crates can either be listed as `crates: [A, B]` or be selected by name with
+//! `A: name: regex("^sp-.*") | "frame-support"`. A feature that is `enabled` in `given` is
+//! assumed to be turned on and everything that it transitively enables is `enabled` in `then`.
+//! Assertions can be negated with `not:`.
+
+pub mod eval;
+pub mod parse;
+
+use regex::Regex;
+
+/// A parsed spec file.
+#[derive(Debug, Clone)]
+pub struct Spec {
+	/// The tests in order of declaration.
+	pub tests: Vec<Test>,
+}
+
+/// A single `test:` block.
+#[derive(Debug, Clone)]
+pub struct Test {
+	/// Human readable description of the test.
+	pub name: String,
+	/// The crate variables and the constraints that select them.
+	pub given: Given,
+	/// What needs to hold for all selected crates.
+	pub then: Assertion,
+	/// What to do if it does not hold.
+	pub otherwise: Otherwise,
+}
+
+/// The `given:` section of a [Test].
+#[derive(Debug, Clone, Default)]
+pub struct Given {
+	/// Crate variables in order of declaration.
+	pub crates: Vec<(String, Selector)>,
+	/// Dependency relations that must hold between the variables.
+	pub dependencies: Vec<DependencyFact>,
+	/// Features that the variables must have.
+	pub features: Vec<FeatureFact>,
+}
+
+/// Selects the crates that a variable can be bound to.
+#[derive(Debug, Clone)]
+pub enum Selector {
+	/// Any crate.
+	Any,
+	/// Any crate whose name matches one of the patterns.
+	Name(Vec<NamePattern>),
+}
+
+/// Matches the name of a crate.
+#[derive(Debug, Clone)]
+pub enum NamePattern {
+	/// Exactly this name.
+	Exact(String),
+	/// Any name that matches the regex.
+	Regex(Regex),
+}
+
+/// A dependency relation between two crate variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyFact {
+	/// The dependant.
+	pub from: String,
+	/// The dependency.
+	pub to: String,
+	/// Whether `to` can also be an indirect dependency of `from`.
+	pub transitive: bool,
+}
+
+/// A fact about the feature of a crate variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeatureFact {
+	/// The crate variable.
+	pub krate: String,
+	/// The feature.
+	pub feature: String,
+	/// Whether the feature is only defined or also enabled.
+	pub enabled: bool,
+}
+
+/// Something that should hold for the bound crate variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Assertion {
+	/// All of the assertions hold.
+	All(Vec<Assertion>),
+	/// The assertion does not hold.
+	Not(Box<Assertion>),
+	/// A dependency relation holds.
+	Dependency(DependencyFact),
+	/// A feature is defined or enabled.
+	Feature(FeatureFact),
+}
+
+/// The `otherwise:` section of a [Test].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Otherwise {
+	/// Error message that is reported for each violation.
+	pub error: String,
+	/// Fixes that are applied with `--fix`.
+	pub fixes: Vec<Fix>,
+}
+
+/// An `auto-fix:` action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fix {
+	/// Add `entry` to `feature` of `krate`.
+	///
+	/// The dependency part of `entry` can be a crate variable.
+	EnableFeature { krate: String, feature: String, entry: String },
+	/// Remove `dependency` from the manifest of `krate`.
+	RemoveDependency { krate: String, dependency: String },
+}
+
+impl NamePattern {
+	/// Whether `name` matches this pattern.
+	pub fn matches(&self, name: &str) -> bool {
+		match self {
+			Self::Exact(exact) => exact == name,
+			Self::Regex(regex) => regex.is_match(name),
+		}
+	}
+}
+
+impl Selector {
+	/// Whether a crate with `name` is selected.
+	pub fn matches(&self, name: &str) -> bool {
+		match self {
+			Self::Any => true,
+			Self::Name(patterns) => patterns.iter().any(|p| p.matches(name)),
+		}
+	}
+}
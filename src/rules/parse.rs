@@ -0,0 +1,437 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// SPDX-FileCopyrightText: Oliver Tale-Yazdi <oliver@tasty.limo>
+
+//! Parse a spec file into a [Spec].
+
+use super::*;
+
+/// A line of the spec split into its `:` separated segments and its indented children.
+#[derive(Debug)]
+struct Line {
+	/// One-based line number for error messages.
+	number: usize,
+	segments: Vec<String>,
+	children: Vec<Line>,
+}
+
+impl Line {
+	fn error<T>(&self, msg: impl std::fmt::Display) -> Result<T, String> {
+		Err(format!("Line {}: {msg}", self.number))
+	}
+
+	fn segments(&self) -> Vec<&str> {
+		self.segments.iter().map(String::as_str).collect()
+	}
+}
+
+/// Parse the content of a spec file.
+pub fn parse(raw: &str) -> Result<Spec, String> {
+	let lines = raw
+		.lines()
+		.enumerate()
+		.filter(|(_, l)| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+		.map(|(i, l)| {
+			let indent = l.len() - l.trim_start().len();
+			let line = Line { number: i + 1, segments: split(l.trim()), children: Vec::new() };
+			(indent, line)
+		})
+		.collect::<Vec<_>>();
+	let mut lines = lines.into_iter().peekable();
+	let roots = nest(&mut lines, 0)?;
+	if let Some((_, line)) = lines.next() {
+		return line.error("Inconsistent indentation")
+	}
+
+	let tests = roots.iter().map(parse_test).collect::<Result<Vec<_>, _>>()?;
+	Ok(Spec { tests })
+}
+
+/// Build the line tree of all lines that are indented by exactly `indent`.
+fn nest<I>(lines: &mut std::iter::Peekable<I>, indent: usize) -> Result<Vec<Line>, String>
+where
+	I: Iterator<Item = (usize, Line)>,
+{
+	let mut block = Vec::<Line>::new();
+
+	while let Some((i, _)) = lines.peek() {
+		let i = *i;
+		if i < indent {
+			break
+		}
+		if i > indent {
+			let Some(parent) = block.last_mut() else {
+				let (_, line) = lines.next().unwrap();
+				return line.error("Unexpected indentation")
+			};
+			if !parent.children.is_empty() {
+				let (_, line) = lines.next().unwrap();
+				return line.error("Inconsistent indentation")
+			}
+			parent.children = nest(lines, i)?;
+			continue
+		}
+		let (_, line) = lines.next().unwrap();
+		block.push(line);
+	}
+
+	Ok(block)
+}
+
+/// Split a line at all `:` that are not inside of quotes or parenthesis.
+///
+/// A trailing `:` opens a block and does not produce an empty segment.
+fn split(line: &str) -> Vec<String> {
+	let mut segments = Vec::new();
+	let (mut current, mut quoted, mut escaped, mut depth) = (String::new(), false, false, 0);
+
+	for c in line.chars() {
+		match c {
+			_ if escaped => escaped = false,
+			'\\' if quoted => escaped = true,
+			'"' => quoted = !quoted,
+			'(' | '[' if !quoted => depth += 1,
+			')' | ']' if !quoted => depth -= 1,
+			':' if !quoted && depth == 0 => {
+				segments.push(current.trim().to_string());
+				current.clear();
+				continue
+			},
+			_ => {},
+		}
+		current.push(c);
+	}
+	if !current.trim().is_empty() || segments.is_empty() {
+		segments.push(current.trim().to_string());
+	}
+
+	segments
+}
+
+fn parse_test(line: &Line) -> Result<Test, String> {
+	let name = match line.segments().as_slice() {
+		["test", name @ ..] if !name.is_empty() => name.join(": "),
+		_ => return line.error("Expected `test: <name>`"),
+	};
+	let (mut given, mut then, mut otherwise) = (None, None, None);
+
+	for child in line.children.iter() {
+		match child.segments().as_slice() {
+			["given"] if given.is_none() => given = Some(parse_given(child)?),
+			["then"] if then.is_none() => then = Some(parse_assertions(child)?),
+			["otherwise"] if otherwise.is_none() => otherwise = Some(parse_otherwise(child)?),
+			_ => return child.error("Expected one `given:`, `then:` or `otherwise:` block"),
+		}
+	}
+	let (Some(given), Some(then), Some(otherwise)) = (given, then, otherwise) else {
+		return line.error("A test needs a `given:`, `then:` and `otherwise:` block")
+	};
+
+	let test = Test { name, given, then, otherwise };
+	validate(&test).or_else(|e| line.error(e))?;
+	Ok(test)
+}
+
+fn parse_given(line: &Line) -> Result<Given, String> {
+	let mut given = Given::default();
+
+	for child in line.children.iter() {
+		match child.segments().as_slice() {
+			["crates", list] =>
+				for var in parse_list(list).or_else(|e| child.error(e))? {
+					given.crates.push((var, Selector::Any));
+				},
+			["crates"] =>
+				for var in child.children.iter() {
+					let selector = match var.segments().as_slice() {
+						[_] => Selector::Any,
+						[_, "name", patterns] =>
+							Selector::Name(parse_patterns(patterns).or_else(|e| var.error(e))?),
+						_ => return var.error("Expected `<crate>: name: <pattern>`"),
+					};
+					given.crates.push((var.segments[0].clone(), selector));
+				},
+			["dependencies"] =>
+				for dep in child.children.iter() {
+					given.dependencies.push(parse_dependency(&dep.segments(), dep)?);
+				},
+			["features"] =>
+				for feature in child.children.iter() {
+					given.features.push(parse_feature(&feature.segments(), feature)?);
+				},
+			_ => return child.error("Expected `crates:`, `dependencies:` or `features:`"),
+		}
+	}
+
+	Ok(given)
+}
+
+/// Parse all children of `line` as assertions that must all hold.
+fn parse_assertions(line: &Line) -> Result<Assertion, String> {
+	line.children
+		.iter()
+		.map(|child| parse_assertion(&child.segments(), child))
+		.collect::<Result<Vec<_>, _>>()
+		.map(Assertion::All)
+}
+
+/// Parse `segments` as assertion. Blocks like `not:` and `dependencies:` continue in the children.
+fn parse_assertion(segments: &[&str], line: &Line) -> Result<Assertion, String> {
+	match segments {
+		["not"] => Ok(Assertion::Not(parse_assertions(line)?.into())),
+		["not", rest @ ..] => Ok(Assertion::Not(parse_assertion(rest, line)?.into())),
+		["dependencies" | "features"] => parse_assertions(line),
+		["dependencies", rest @ ..] => Ok(Assertion::Dependency(parse_dependency(rest, line)?)),
+		["features", rest @ ..] => Ok(Assertion::Feature(parse_feature(rest, line)?)),
+		[_, "direct" | "transitive", _] =>
+			Ok(Assertion::Dependency(parse_dependency(segments, line)?)),
+		[_, "enabled" | "defines", _] => Ok(Assertion::Feature(parse_feature(segments, line)?)),
+		_ => line.error("Expected an assertion"),
+	}
+}
+
+fn parse_dependency(segments: &[&str], line: &Line) -> Result<DependencyFact, String> {
+	match segments {
+		[from, kind @ ("direct" | "transitive"), to] => Ok(DependencyFact {
+			from: from.to_string(),
+			to: to.to_string(),
+			transitive: *kind == "transitive",
+		}),
+		_ => line.error("Expected `<crate>: direct|transitive: <crate>`"),
+	}
+}
+
+fn parse_feature(segments: &[&str], line: &Line) -> Result<FeatureFact, String> {
+	match segments {
+		[krate, kind @ ("enabled" | "defines"), feature] => Ok(FeatureFact {
+			krate: krate.to_string(),
+			feature: feature.to_string(),
+			enabled: *kind == "enabled",
+		}),
+		_ => line.error("Expected `<crate>: enabled|defines: <feature>`"),
+	}
+}
+
+fn parse_otherwise(line: &Line) -> Result<Otherwise, String> {
+	let (mut error, mut fixes) = (None, Vec::new());
+
+	for child in line.children.iter() {
+		match child.segments().as_slice() {
+			["error", msg] if error.is_none() => {
+				error = Some(parse_string(msg).or_else(|e| child.error(e))?);
+				for fix in child.children.iter() {
+					fixes.push(parse_fix(fix)?);
+				}
+			},
+			["auto-fix", ..] => fixes.push(parse_fix(child)?),
+			_ => return child.error("Expected one `error: \"<message>\"`"),
+		}
+	}
+	let Some(error) = error else { return line.error("Missing `error:`") };
+
+	Ok(Otherwise { error, fixes })
+}
+
+fn parse_fix(line: &Line) -> Result<Fix, String> {
+	match line.segments().as_slice() {
+		["auto-fix", "enable-feature", krate, feature, entry] => Ok(Fix::EnableFeature {
+			krate: krate.to_string(),
+			feature: feature.to_string(),
+			entry: entry.to_string(),
+		}),
+		["auto-fix", "remove-dependency", krate, dependency] => Ok(Fix::RemoveDependency {
+			krate: krate.to_string(),
+			dependency: dependency.to_string(),
+		}),
+		_ => line.error(
+			"Expected `auto-fix: enable-feature: <crate>: <feature>: <entry>` or `auto-fix: remove-dependency: <crate>: <crate>`",
+		),
+	}
+}
+
+/// Parse a list like `[A, B]`.
+fn parse_list(raw: &str) -> Result<Vec<String>, String> {
+	let Some(inner) = raw.strip_prefix('[').and_then(|r| r.strip_suffix(']')) else {
+		return Err(format!("Expected a list like `[A, B]` but got `{raw}`"))
+	};
+
+	Ok(inner
+		.split(',')
+		.map(str::trim)
+		.filter(|s| !s.is_empty())
+		.map(Into::into)
+		.collect())
+}
+
+/// Parse `|` separated name patterns like `regex("^sp-.*") | "frame-support"`.
+fn parse_patterns(raw: &str) -> Result<Vec<NamePattern>, String> {
+	let mut patterns = Vec::new();
+	let (mut current, mut quoted, mut escaped) = (String::new(), false, false);
+
+	for c in raw.chars().chain(std::iter::once('|')) {
+		match c {
+			_ if escaped => escaped = false,
+			'\\' if quoted => escaped = true,
+			'"' => quoted = !quoted,
+			'|' if !quoted => {
+				patterns.push(parse_pattern(current.trim())?);
+				current.clear();
+				continue
+			},
+			_ => {},
+		}
+		current.push(c);
+	}
+
+	Ok(patterns)
+}
+
+fn parse_pattern(raw: &str) -> Result<NamePattern, String> {
+	if let Some(inner) = raw.strip_prefix("regex(").and_then(|r| r.strip_suffix(')')) {
+		let regex = parse_string(inner.trim())?;
+		return Regex::new(&regex)
+			.map(NamePattern::Regex)
+			.map_err(|e| format!("Invalid regex `{regex}`: {e}"))
+	}
+	if raw.starts_with('"') {
+		return parse_string(raw).map(NamePattern::Exact)
+	}
+	if raw.is_empty() {
+		return Err("Empty name pattern".into())
+	}
+
+	Ok(NamePattern::Exact(raw.into()))
+}
+
+/// Parse a double quoted string with `\"` and `\\` escapes.
+fn parse_string(raw: &str) -> Result<String, String> {
+	let Some(inner) = raw.strip_prefix('"').and_then(|r| r.strip_suffix('"')) else {
+		return Err(format!("Expected a quoted string but got `{raw}`"))
+	};
+	let (mut out, mut escaped) = (String::new(), false);
+
+	for c in inner.chars() {
+		match c {
+			'\\' if !escaped => escaped = true,
+			_ => {
+				out.push(c);
+				escaped = false;
+			},
+		}
+	}
+
+	Ok(out)
+}
+
+/// Check that all used crate variables are declared.
+fn validate(test: &Test) -> Result<(), String> {
+	let declared = test.given.crates.iter().map(|(v, _)| v.as_str()).collect::<Vec<_>>();
+	let mut used = Vec::new();
+
+	for dep in test.given.dependencies.iter() {
+		used.extend([&dep.from, &dep.to]);
+	}
+	for feature in test.given.features.iter() {
+		used.push(&feature.krate);
+	}
+	let mut assertions = vec![&test.then];
+	while let Some(assertion) = assertions.pop() {
+		match assertion {
+			Assertion::All(all) => assertions.extend(all),
+			Assertion::Not(inner) => assertions.push(inner),
+			Assertion::Dependency(dep) => used.extend([&dep.from, &dep.to]),
+			Assertion::Feature(feature) => used.push(&feature.krate),
+		}
+	}
+	for fix in test.otherwise.fixes.iter() {
+		match fix {
+			Fix::EnableFeature { krate, .. } => used.push(krate),
+			Fix::RemoveDependency { krate, dependency } => used.extend([krate, dependency]),
+		}
+	}
+
+	match used.into_iter().find(|v| !declared.contains(&v.as_str())) {
+		Some(var) => Err(format!("Crate `{var}` is not declared in `given: crates:`")),
+		None => Ok(()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_spec_file_works() {
+		let raw = std::fs::read_to_string("spec.toml").unwrap();
+		let spec = parse(&raw).unwrap();
+		assert_eq!(spec.tests.len(), 3);
+
+		let propagate = &spec.tests[0];
+		assert_eq!(propagate.name, "Check that the feature propagates.");
+		assert_eq!(propagate.given.crates.len(), 2);
+		assert_eq!(
+			propagate.given.dependencies,
+			vec![DependencyFact { from: "A".into(), to: "B".into(), transitive: false }]
+		);
+		assert_eq!(propagate.given.features.len(), 2);
+		assert_eq!(
+			propagate.then,
+			Assertion::All(vec![Assertion::Feature(FeatureFact {
+				krate: "B".into(),
+				feature: "runtime-benchmarks".into(),
+				enabled: true,
+			})])
+		);
+		assert_eq!(
+			propagate.otherwise.fixes,
+			vec![Fix::EnableFeature {
+				krate: "A".into(),
+				feature: "runtime-benchmarks".into(),
+				entry: "B/runtime-benchmarks".into(),
+			}]
+		);
+
+		let transitive = &spec.tests[2];
+		let Selector::Name(patterns) = &transitive.given.crates[1].1 else { panic!() };
+		assert_eq!(patterns.len(), 2);
+		assert!(patterns[1].matches("pallet-balances"));
+		assert!(!patterns[1].matches("sp-runtime"));
+		assert_eq!(
+			transitive.then,
+			Assertion::All(vec![Assertion::Not(
+				Assertion::All(vec![Assertion::Dependency(DependencyFact {
+					from: "A".into(),
+					to: "B".into(),
+					transitive: true,
+				})])
+				.into()
+			)])
+		);
+		assert_eq!(
+			transitive.otherwise.fixes,
+			vec![Fix::RemoveDependency { krate: "A".into(), dependency: "B".into() }]
+		);
+	}
+
+	#[test]
+	fn split_works() {
+		assert_eq!(split("then:"), vec!["then"]);
+		assert_eq!(split("A: direct: B"), vec!["A", "direct", "B"]);
+		assert_eq!(split(r#"error: "a: b""#), vec!["error", r#""a: b""#]);
+		assert_eq!(split(r#"A: name: regex("^a:b")"#), vec!["A", "name", r#"regex("^a:b")"#]);
+	}
+
+	#[test]
+	fn undeclared_crate_errors() {
+		let raw = "test: t\n\tgiven:\n\t\tcrates: [A]\n\tthen:\n\t\tA: direct: B\n\totherwise:\n\t\terror: \"e\"\n";
+		assert_eq!(
+			parse(raw).unwrap_err(),
+			"Line 1: Crate `B` is not declared in `given: crates:`"
+		);
+	}
+
+	#[test]
+	fn inconsistent_indentation_errors() {
+		let raw = "test: t\n    given:\n        crates: [A]\n      features:\n";
+		assert_eq!(parse(raw).unwrap_err(), "Line 4: Inconsistent indentation");
+	}
+}
@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// SPDX-FileCopyrightText: Oliver Tale-Yazdi <oliver@tasty.limo>
+
+//! Render lint findings in the [SARIF](https://sarifweb.azurewebsites.net/) format.
+//!
+//! This allows code-scanning UIs to show the findings next to the offending `Cargo.toml` line.
+
+use crate::cmd::lint::{Finding, FindingKind};
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// All lint kinds that are reported as SARIF rules.
+const KINDS: [FindingKind; 4] = [
+	FindingKind::MissingFeature,
+	FindingKind::MissingPropagation,
+	FindingKind::Unused,
+	FindingKind::ImplicitDefault,
+];
+
+/// Convert `findings` into a SARIF log.
+///
+/// Manifest paths are made relative to `root` when possible. The `feature_selection` that the
+/// analysis is based on is recorded in the properties of the run.
+pub fn to_sarif(findings: &[Finding], root: &Path, feature_selection: &str) -> Value {
+	let rules = KINDS
+		.iter()
+		.map(|kind| {
+			json!({
+				"id": kind.rule_id(),
+				"shortDescription": { "text": kind.description() },
+				"defaultConfiguration": { "level": level(kind) },
+			})
+		})
+		.collect::<Vec<_>>();
+	let results = findings.iter().map(|finding| result(finding, root)).collect::<Vec<_>>();
+
+	json!({
+		"$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+		"version": "2.1.0",
+		"runs": [{
+			"tool": {
+				"driver": {
+					"name": env!("CARGO_PKG_NAME"),
+					"version": env!("CARGO_PKG_VERSION"),
+					"informationUri": env!("CARGO_PKG_REPOSITORY"),
+					"rules": rules,
+				}
+			},
+			"results": results,
+			"properties": { "featureSelection": feature_selection },
+		}]
+	})
+}
+
+/// Convert a single finding into a SARIF result.
+fn result(finding: &Finding, root: &Path) -> Value {
+	let line = std::fs::read_to_string(&finding.manifest_path)
+		.map(|raw| feature_line(&raw, &finding.feature))
+		.unwrap_or(1);
+	let manifest = Path::new(&finding.manifest_path);
+	let artifact = match manifest.strip_prefix(root) {
+		Ok(relative) => json!({ "uri": relative.display().to_string(), "uriBaseId": "%SRCROOT%" }),
+		Err(_) => json!({ "uri": format!("file://{}", manifest.display()) }),
+	};
+	let message = match finding.kind {
+		FindingKind::MissingFeature => format!(
+			"crate {:?} must have feature {:?} because {} dependencies have it: {}",
+			finding.name,
+			finding.feature,
+			finding.dependencies.len(),
+			finding.dependencies.join(", ")
+		),
+		FindingKind::MissingPropagation => format!(
+			"crate {:?} must propagate feature {:?} to: {}",
+			finding.name,
+			finding.feature,
+			finding.dependencies.join(", ")
+		),
+		FindingKind::Unused => {
+			format!("crate {:?} does not use feature {:?}", finding.name, finding.feature)
+		},
+		FindingKind::ImplicitDefault => format!(
+			"crate {:?} relies on the default features to enable feature {:?} of: {}",
+			finding.name,
+			finding.feature,
+			finding.dependencies.join(", ")
+		),
+	};
+
+	json!({
+		"ruleId": finding.kind.rule_id(),
+		"level": level(&finding.kind),
+		"message": { "text": message },
+		"locations": [{
+			"physicalLocation": {
+				"artifactLocation": artifact,
+				"region": { "startLine": line },
+			}
+		}],
+	})
+}
+
+/// The SARIF level of a finding kind.
+fn level(kind: &FindingKind) -> &'static str {
+	if kind.is_error() {
+		"error"
+	} else {
+		"warning"
+	}
+}
+
+/// Find the one-based line of `feature` in the `[features]` table of a raw manifest.
+///
+/// Falls back to the table header if the feature does not exist and to the first line if there is
+/// no such table.
+fn feature_line(raw: &str, feature: &str) -> usize {
+	let mut table = None;
+
+	for (i, line) in raw.lines().enumerate() {
+		let line = line.trim();
+		if line.starts_with('[') {
+			if table.is_some() {
+				break
+			}
+			if !line.starts_with("[[") &&
+				line.trim_start_matches('[').trim_end_matches(']').trim() == "features"
+			{
+				table = Some(i + 1);
+			}
+			continue
+		}
+		if table.is_none() {
+			continue
+		}
+		let Some((key, _)) = line.split_once('=') else { continue };
+		if key.trim().trim_matches('"') == feature {
+			return i + 1
+		}
+	}
+
+	table.unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn feature_line_works() {
+		let raw = r#"[package]
+name = "std"
+
+[features]
+default = ["std"]
+"std" = [
+	"frame-support/std",
+]
+runtime-benchmarks = []
+
+[dependencies]
+try-runtime = "1"
+"#;
+		assert_eq!(feature_line(raw, "std"), 6);
+		assert_eq!(feature_line(raw, "runtime-benchmarks"), 9);
+		assert_eq!(feature_line(raw, "try-runtime"), 4, "Falls back to the table");
+		assert_eq!(feature_line("[package]", "std"), 1, "Falls back to the first line");
+	}
+}
@@ -15,40 +15,85 @@ lazy_static::lazy_static! {
 
 #[test]
 fn substrate_trace_works() {
+	let paths = trace(&[]);
+	assert_eq!(paths.len(), 1, "Only the shortest path: {paths:?}");
+	let shortest = &paths[0];
+	assert_eq!(shortest.first().map(String::as_str), Some("node-cli"));
+	assert_eq!(shortest.last().map(String::as_str), Some("snow"));
+
+	// The shortest path is stable across runs.
+	assert_eq!(&trace(&[]).remove(0), shortest);
+	// No other path is shorter.
+	let limited = trace(&["--limit", "5"]);
+	assert_eq!(shortest.len(), limited[0].len(), "{shortest:?} vs {limited:?}");
+	assert_eq!(&limited[0], shortest);
+	assert!(limited.iter().all(|path| path.len() >= shortest.len()), "{limited:?}");
+	let any = trace(&["--any"]).remove(0);
+	assert!(shortest.len() <= any.len(), "{shortest:?} is longer than {any:?}");
+}
+
+#[test]
+fn substrate_trace_any_works() {
+	let paths = trace(&["--any", "--show-kind"]);
+	assert_eq!(paths.len(), 1, "Only one path: {paths:?}");
+	let path = &paths[0];
+	assert_eq!(path.first().map(String::as_str), Some("node-cli"));
+	assert!(path.last().is_some_and(|last| last.ends_with(" snow")), "{path:?}");
+	// Only `node-cli` itself builds its dev-dependencies.
+	assert!(path.iter().skip(2).all(|krate| !krate.starts_with("[dev] ")), "{path:?}");
+}
+
+#[test]
+fn substrate_lint_works() {
 	let substrate = SUBSTRATE.lock().unwrap();
+	let lint = |format: &str| {
+		let mut cmd = std::process::Command::new(cargo_bin("feature"));
+		cmd.args(["lint", "propagate-feature", "--format", format]);
+		cmd.arg("--manifest-path");
+		cmd.arg(substrate.join("Cargo.toml"));
+		cmd.arg("--workspace");
+		cmd.args(["--feature", "runtime-benchmarks"]);
 
-	let mut cmd = std::process::Command::new(cargo_bin("feature"));
-	cmd.arg("trace");
-	cmd.arg("--manifest-path");
-	cmd.arg(substrate.join("Cargo.toml"));
-	cmd.arg("node-cli");
-	cmd.arg("snow");
+		let output = cmd.output().unwrap();
+		// Exit code 1 means that the lint ran through and found problems.
+		if output.status.code() != Some(1) {
+			panic!(
+				"Command failed with status {:?}: {}",
+				output.status,
+				String::from_utf8_lossy(&output.stderr)
+			);
+		}
+		String::from_utf8(output.stdout).unwrap()
+	};
 
-	let output = cmd.output().unwrap();
-	if !output.status.success() {
-		panic!(
-			"Command failed with status {:?}: {}",
-			output.status,
-			String::from_utf8_lossy(&output.stderr)
-		);
-	}
-	let stdout = String::from_utf8_lossy(&output.stdout);
-	let want = "node-cli -> try-runtime-cli -> substrate-rpc-client -> sp-runtime -> substrate-test-runtime-client -> substrate-test-runtime -> sc-service -> sc-telemetry -> libp2p -> libp2p-webrtc -> libp2p-noise -> snow";
-	if !stdout.contains(want) {
+	// The numbers depend on the Substrate revision, so only check that the summary is consistent.
+	let json: serde_json::Value = serde_json::from_str(&lint("json")).unwrap();
+	assert_eq!(json["feature_selection"], "all features");
+	let findings = json["findings"].as_array().expect("Findings are an array");
+	let errors = findings
+		.iter()
+		.filter(|f| matches!(f["kind"].as_str(), Some("missing-feature" | "missing-propagation")))
+		.count();
+	let warnings = findings.len() - errors;
+	assert!(errors > 0, "Substrate has missing features");
+
+	let stdout = lint("human");
+	let want = format!("Generated {errors} errors and {warnings} warnings and fixed 0 issues.");
+	if !stdout.contains(&want) {
 		panic!("Unexpected output: {stdout}");
 	}
 }
 
-#[test]
-fn substrate_lint_works() {
+/// Trace from `node-cli` to `snow` and return the crate names of all printed paths.
+fn trace(args: &[&str]) -> Vec<Vec<String>> {
 	let substrate = SUBSTRATE.lock().unwrap();
 
 	let mut cmd = std::process::Command::new(cargo_bin("feature"));
-	cmd.args(["lint", "propagate-feature"]);
+	cmd.arg("trace").args(args);
 	cmd.arg("--manifest-path");
 	cmd.arg(substrate.join("Cargo.toml"));
-	cmd.arg("--workspace");
-	cmd.args(["--feature", "runtime-benchmarks"]);
+	cmd.arg("node-cli");
+	cmd.arg("snow");
 
 	let output = cmd.output().unwrap();
 	if !output.status.success() {
@@ -59,10 +104,11 @@ fn substrate_lint_works() {
 		);
 	}
 	let stdout = String::from_utf8_lossy(&output.stdout);
-	let want = "Generated 185 errors and 0 warnings and fixed 0 issues.";
-	if !stdout.contains(want) {
-		panic!("Unexpected output: {stdout}");
-	}
+	stdout
+		.lines()
+		.filter(|line| line.contains(" -> "))
+		.map(|line| line.split(" -> ").map(String::from).collect())
+		.collect()
 }
 
 fn clone_repo(repo: &str, rev: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
@@ -0,0 +1,601 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// SPDX-FileCopyrightText: Oliver Tale-Yazdi <oliver@tasty.limo>
+
+//! Integration tests using the workspaces in `tests/fixtures`.
+
+#![cfg(test)]
+
+use assert_cmd::cargo::cargo_bin;
+use rstest::*;
+use std::{
+	path::Path,
+	process::{Command, Output},
+};
+
+#[rstest]
+#[case::resolve_graph(false)]
+#[case::workspace(true)]
+fn renamed_dependencies_are_reported(#[case] workspace: bool) {
+	let dir = fixture("renamed");
+	let mut args = vec!["lint", "propagate-feature", "--feature", "std", "-p", "root"];
+	if workspace {
+		args.push("--workspace");
+	}
+
+	let output = run(dir.path(), &args);
+	assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let want = "    must propagate to:\n      codec\n      opt\n      tgt\n";
+	assert!(stdout.contains(want), "Unexpected output: {stdout}");
+}
+
+#[test]
+fn target_dependencies_are_filtered() {
+	let dir = fixture("renamed");
+	let lint = ["lint", "propagate-feature", "--feature", "std", "-p", "root"];
+	let output = run(dir.path(), &[&lint[..], &["--target", "wasm32-unknown-unknown"]].concat());
+	assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let want = "    must propagate to:\n      codec\n      opt\nGenerated";
+	assert!(stdout.contains(want), "Unexpected output: {stdout}");
+}
+
+#[test]
+fn feature_selection_is_respected() {
+	let dir = fixture("renamed");
+	let output = run(
+		dir.path(),
+		&["lint", "propagate-feature", "--feature", "std", "-p", "root", "--features", "default"],
+	);
+	assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(stdout.contains("Using default features\n"), "Unexpected output: {stdout}");
+	// The optional dependency is not enabled by default.
+	let want = "    must propagate to:\n      codec\n      tgt\nGenerated";
+	assert!(stdout.contains(want), "Unexpected output: {stdout}");
+}
+
+#[test]
+fn renamed_dependencies_are_fixed() {
+	let dir = fixture("renamed");
+	let lint = |args: &[&str]| {
+		let lint = ["lint", "propagate-feature", "--feature", "std", "-p", "root"];
+		run(dir.path(), &[&lint, args].concat())
+	};
+
+	let output = lint(&["--fix"]);
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+	let manifest = std::fs::read_to_string(dir.path().join("root/Cargo.toml")).unwrap();
+	for entry in ["\"codec/std\"", "\"opt?/std\"", "\"tgt/std\""] {
+		assert!(manifest.contains(entry), "Missing {entry} in:\n{manifest}");
+	}
+
+	let output = lint(&[]);
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn allow_directives_survive_fixes() {
+	let dir = fixture("renamed");
+	let manifest = dir.path().join("root/Cargo.toml");
+	let raw = std::fs::read_to_string(&manifest).unwrap();
+	let raw = raw.replace("std = []", "std = [\n\t# feature: allow(tgt)\n\t\"codec/std\",\n]");
+	std::fs::write(&manifest, raw).unwrap();
+	let lint = |args: &[&str]| {
+		let lint = ["lint", "propagate-feature", "--feature", "std", "-p", "root"];
+		run(dir.path(), &[&lint, args].concat())
+	};
+
+	let output = lint(&["--fix"]);
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+	let raw = std::fs::read_to_string(&manifest).unwrap();
+	let want = "std = [\n\t# feature: allow(tgt)\n\t\"codec/std\",\n\t\"opt?/std\"\n]\n";
+	assert!(raw.ends_with(want), "Unexpected manifest: {raw}");
+
+	let output = lint(&[]);
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn renamed_dependencies_are_traced() {
+	let dir = fixture("renamed");
+	let output = run(dir.path(), &["trace", "root", "dep-c"]);
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(stdout.contains("root -> dep-c"), "Unexpected output: {stdout}");
+}
+
+#[test]
+fn metadata_file_works() {
+	let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/renamed.json");
+	let mut cmd = Command::new(cargo_bin("feature"));
+	cmd.args(["trace", "root", "dep-c"]).arg("--metadata-file").arg(file);
+
+	let output = cmd.output().unwrap();
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(stdout.contains("root -> dep-c"), "Unexpected output: {stdout}");
+}
+
+#[test]
+fn metadata_stdin_works() {
+	use std::{io::Write, process::Stdio};
+	let raw =
+		std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/renamed.json"))
+			.unwrap();
+	let mut cmd = Command::new(cargo_bin("feature"));
+	cmd.args([
+		"lint",
+		"forbid-dependency",
+		"--metadata-file",
+		"-",
+		"--from",
+		"^root$",
+		"--to",
+		"^dep-",
+	]);
+
+	let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+	child.stdin.take().unwrap().write_all(&raw).unwrap();
+	let output = child.wait_with_output().unwrap();
+	assert_eq!(output.status.code(), Some(1));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	// The optional dependency is not part of the saved metadata.
+	assert!(stdout.contains("Found 2 forbidden dependencies."), "Unexpected output: {stdout}");
+}
+
+#[rstest]
+#[case::default_kinds("std", &[], &["dev", "normal"])]
+#[case::configured_kinds("runtime-benchmarks", &[], &["normal"])]
+#[case::selected_kinds("std", &["--dep-kinds", "normal,build"], &["normal"])]
+fn dep_kinds_are_respected(#[case] feature: &str, #[case] args: &[&str], #[case] want: &[&str]) {
+	let dir = fixture("kinds");
+	let lint = ["lint", "propagate-feature", "--feature", feature, "-p", "root"];
+	let output = run(dir.path(), &[&lint, args].concat());
+	assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let want = format!("    must propagate to:\n      {}\nGenerated", want.join("\n      "));
+	assert!(stdout.contains(&want), "Unexpected output: {stdout}");
+}
+
+#[test]
+fn dep_kinds_are_traced() {
+	let dir = fixture("kinds");
+	let output = run(dir.path(), &["trace", "--show-kind", "root", "build"]);
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(stdout.contains("root -> [build] build"), "Unexpected output: {stdout}");
+}
+
+#[rstest]
+#[case::shortest(&[], "root -> leaf\n")]
+#[case::limit(&["--limit", "2"], "root -> leaf\nroot -> a -> c -> leaf\n")]
+#[case::all(&["--all"], "root -> a -> c -> leaf\nroot -> b -> c -> leaf\nroot -> leaf\n")]
+#[case::collapse(&["--all", "--collapse"], "root -> a -> c -> leaf\nroot -> b -> c -> ...\nroot -> leaf\n")]
+fn multiple_paths_are_traced(#[case] args: &[&str], #[case] want: &str) {
+	let dir = fixture("paths");
+	let output = run(dir.path(), &[&["trace", "root", "leaf"], args].concat());
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(stdout.ends_with(want), "Unexpected output: {stdout}");
+}
+
+#[rstest]
+#[case::first_hop(&["b", "c", "--show-kind"], "b -> [dev] c\n")]
+#[case::later_hop(&["a", "c", "--all"], "")]
+#[case::cut(&["a", "c", "--cut"], "")]
+fn dev_dependencies_are_only_traced_from_the_start(#[case] args: &[&str], #[case] want: &str) {
+	let dir = fixture("dev");
+	let output = run(dir.path(), &[&["trace"], args].concat());
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	// `a` never builds the dev-dependencies of `b`.
+	assert_eq!(output.status.success(), !want.is_empty(), "Unexpected output: {stdout}");
+	assert!(stdout.ends_with(want) && !stdout.contains("a -> b"), "Unexpected output: {stdout}");
+	if want.is_empty() {
+		let stderr = String::from_utf8_lossy(&output.stderr);
+		assert_eq!(output.status.code(), Some(2));
+		assert!(stderr.contains("No path found from a to c"), "{stderr}");
+	}
+}
+
+#[rstest]
+#[case::from(&["trace", "nope", "c"], "Could not find crate nope in the left dependency graph")]
+#[case::to(&["trace", "a", "nope"], "Could not find crate nope in the right dependency graph")]
+#[case::packages(&["lint", "propagate-feature", "--feature", "std", "-p", "nope"], "No packages found: [\"nope\"]")]
+fn unknown_crates_are_errors(#[case] args: &[&str], #[case] want: &str) {
+	let dir = fixture("dev");
+	let output = run(dir.path(), args);
+	assert_eq!(output.status.code(), Some(2));
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	assert!(stderr.contains(want) && !stderr.contains("panicked"), "{stderr}");
+}
+
+#[test]
+fn zero_limit_is_rejected() {
+	let dir = fixture("paths");
+	let output = run(dir.path(), &["trace", "root", "leaf", "--limit", "0"]);
+	assert_eq!(output.status.code(), Some(2));
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	assert!(stderr.contains("invalid value '0' for '--limit <LIMIT>'"), "{stderr}");
+}
+
+#[rstest]
+#[case::node(&["--avoid", "c"], "root -> leaf\n")]
+#[case::edge(&["--avoid-edge", "root:leaf", "--limit", "3"], "root -> a -> c -> leaf\nroot -> b -> c -> leaf\n")]
+#[case::disconnected(&["--avoid", "c", "--avoid-edge", "root:leaf"], "Avoiding c, root:leaf disconnects root from leaf\n")]
+fn avoided_crates_are_not_traced(#[case] args: &[&str], #[case] want: &str) {
+	let dir = fixture("paths");
+	let output = run(dir.path(), &[&["trace", "root", "leaf"], args].concat());
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(stdout.ends_with(want), "Unexpected output: {stdout}");
+}
+
+#[test]
+fn min_cut_is_reported() {
+	let dir = fixture("paths");
+	let output = run(dir.path(), &["trace", "--cut", "root", "leaf"]);
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let manifest = |name: &str| dir.path().join(name).join("Cargo.toml").display().to_string();
+	let want = format!(
+		"Removing these 2 dependencies disconnects root from leaf:\n  c -> leaf in {}\n  root -> leaf in {}\n",
+		manifest("c"),
+		manifest("root")
+	);
+	assert!(stdout.ends_with(&want), "Unexpected output: {stdout}");
+}
+
+#[rstest]
+#[case::direct(&[], "crates that depend on leaf:\n  c\n  root\n")]
+#[case::transitive(&["--transitive"], "  c (1 hop)\n  root (1 hop)\n  a (2 hops)\n  b (2 hops)\n")]
+#[case::depth(&["--depth", "2"], "  c (1 hop)\n  root (1 hop)\n  a (2 hops)\n  b (2 hops)\n")]
+fn reverse_dependencies_are_listed(#[case] args: &[&str], #[case] want: &str) {
+	let dir = fixture("paths");
+	let output = run(dir.path(), &[&["rdeps", "leaf"], args].concat());
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(stdout.ends_with(want), "Unexpected output: {stdout}");
+}
+
+#[test]
+fn dev_dependants_are_not_listed_transitively() {
+	let dir = fixture("dev");
+	let output = run(dir.path(), &["rdeps", "c", "--transitive"]);
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	// Only `b` builds its dev-dependency `c`, its dependant `a` does not.
+	let want = "Found 1 workspace crates that depend on c:\n  b (1 hop)\n";
+	assert!(stdout.ends_with(want), "Unexpected output: {stdout}");
+}
+
+#[test]
+fn zero_depth_is_rejected() {
+	let dir = fixture("paths");
+	let output = run(dir.path(), &["rdeps", "leaf", "--depth", "0"]);
+	assert_eq!(output.status.code(), Some(2));
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	assert!(stderr.contains("invalid value '0' for '--depth <DEPTH>'"), "{stderr}");
+}
+
+#[rstest]
+#[case::clean(&["--from", "^root$", "--to", "^c$"], 0, "Using all features\n")]
+#[case::direct(&[], 1, "crate \"a\" must not depend on \"c\"\n  a -> c\ncrate \"root\" must not depend on \"leaf\"\n  root -> leaf\nFound 2 forbidden dependencies.\n")]
+#[case::transitive(&["--transitive"], 1, "crate \"a\" must not depend on \"c\"\n  a -> c\ncrate \"a\" must not depend on \"leaf\"\n  a -> c -> leaf\ncrate \"root\" must not depend on \"c\"\n  root -> b -> c\ncrate \"root\" must not depend on \"leaf\"\n  root -> leaf\nFound 4 forbidden dependencies.\n")]
+fn forbidden_dependencies_are_reported(
+	#[case] args: &[&str],
+	#[case] code: i32,
+	#[case] want: &str,
+) {
+	let dir = fixture("paths");
+	let mut lint = vec!["lint", "forbid-dependency"];
+	if args.iter().all(|a| *a != "--from") {
+		lint.extend(["--from", "^(root|a)$", "--to", "^(c|leaf)$"]);
+	}
+	let output = run(dir.path(), &[&lint, args].concat());
+	assert_eq!(output.status.code(), Some(code), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(stdout.ends_with(want), "Unexpected output: {stdout}");
+}
+
+#[rstest]
+#[case::own(&["--from", "^b$"], 1, "crate \"b\" must not depend on \"c\"\n  b -> c\nFound 1 forbidden dependencies.\n")]
+#[case::of_dependency(&["--from", "^a$"], 0, "Using all features\n")]
+fn forbidden_dev_dependencies_are_reported(
+	#[case] args: &[&str],
+	#[case] code: i32,
+	#[case] want: &str,
+) {
+	let dir = fixture("dev");
+	let lint = ["lint", "forbid-dependency", "--to", "^c$", "--transitive"];
+	let output = run(dir.path(), &[&lint, args].concat());
+	assert_eq!(output.status.code(), Some(code), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(stdout.ends_with(want), "Unexpected output: {stdout}");
+}
+
+#[test]
+fn metadata_is_cached() {
+	let dir = fixture("renamed");
+	let trace = |args: &[&str]| run(dir.path(), &[&["trace", "root", "dep-c"], args].concat());
+
+	assert!(trace(&[]).status.success());
+	let cache = dir.path().join("target/feature-cache");
+	assert_eq!(std::fs::read_dir(&cache).unwrap().count(), 1);
+	assert!(trace(&[]).status.success());
+	assert_eq!(std::fs::read_dir(&cache).unwrap().count(), 1, "The cache is reused");
+
+	// Changing a manifest invalidates the cache.
+	let manifest = dir.path().join("root/Cargo.toml");
+	let raw = std::fs::read_to_string(&manifest).unwrap();
+	std::fs::write(&manifest, raw.replace("tgt = ", "# tgt = ")).unwrap();
+	assert!(!trace(&[]).status.success(), "dep-c is not a dependency anymore");
+
+	std::fs::remove_dir_all(&cache).unwrap();
+	assert!(!trace(&["--no-cache"]).status.success());
+	assert!(!cache.exists(), "--no-cache does not write the cache");
+}
+
+#[rstest]
+#[case::clean("chain", &["--feature", "unknown"], 0)]
+#[case::errors("chain", &["--feature", "std"], 1)]
+#[case::warnings("defaults", &["--feature", "std"], 0)]
+#[case::denied_warnings("defaults", &["--feature", "std", "--deny", "warnings"], 1)]
+#[case::failure("chain", &["--feature", "std", "--config", "missing.toml"], 2)]
+fn exit_code_works(#[case] fixture_name: &str, #[case] args: &[&str], #[case] code: i32) {
+	let dir = fixture(fixture_name);
+	let output = run(dir.path(), &[&["lint", "propagate-feature"], args].concat());
+	assert_eq!(output.status.code(), Some(code), "{}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[rstest]
+#[case::unchanged("json", None, 0)]
+#[case::unchanged_sarif("sarif", None, 0)]
+#[case::untracked("json", Some("untracked"), 1)]
+#[case::workspace("json", Some("workspace"), 1)]
+fn changed_crates_are_checked(
+	#[case] format: &str,
+	#[case] change: Option<&str>,
+	#[case] found: usize,
+) {
+	let dir = fixture("chain");
+	let git = |args: &[&str]| {
+		let status = Command::new("git")
+			.args(["-c", "user.name=a", "-c", "user.email=a@b"])
+			.args(args)
+			.current_dir(dir.path())
+			.status()
+			.unwrap();
+		assert!(status.success(), "git {args:?}");
+	};
+	git(&["init", "-q"]);
+	git(&["add", "-A"]);
+	git(&["commit", "-qm", "init"]);
+	match change {
+		// Only `b` is missing the feature and it is not tracked anymore.
+		Some("untracked") => git(&["rm", "-q", "--cached", "b/Cargo.toml"]),
+		Some("workspace") => {
+			let manifest = dir.path().join("Cargo.toml");
+			let raw = std::fs::read_to_string(&manifest).unwrap();
+			std::fs::write(&manifest, raw + "\n[patch.crates-io]\n").unwrap();
+		},
+		_ => {},
+	}
+
+	let lint = ["lint", "propagate-feature", "--feature", "std", "--changed-since", "HEAD"];
+	let output = run(dir.path(), &[&lint[..], &["--format", format]].concat());
+	assert_eq!(
+		output.status.code(),
+		Some(found as i32),
+		"{}",
+		String::from_utf8_lossy(&output.stderr)
+	);
+	let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	let findings = match format {
+		"sarif" => &json["runs"][0]["results"],
+		_ => &json["findings"],
+	};
+	assert_eq!(findings.as_array().unwrap().len(), found, "Unexpected output: {json}");
+}
+
+#[test]
+fn unused_features_are_reported() {
+	let dir = fixture("unused");
+	let output = run(dir.path(), &["lint", "propagate-feature", "--feature", "std"]);
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	// `forward` forwards it, a dependant enables it in `dep` and `allowed` allows it.
+	let want = "Using all features\ncrate \"lonely\"\n  feature \"std\"\n    is not used\nGenerated 0 errors and 1 warnings and fixed 0 issues. Suppressed 1 findings.\n";
+	assert!(stdout.ends_with(want), "Unexpected output: {stdout}");
+}
+
+#[rstest]
+#[case::warning(&[], true, 0, "    is implicitly enabled via default:\n      dep\nGenerated 0 errors and 1 warnings")]
+#[case::denied(&["--deny", "warnings"], true, 1, "    is implicitly enabled via default:\n      dep\nGenerated 0 errors and 1 warnings")]
+#[case::no_default_features(&[], false, 1, "    must propagate to:\n      dep\nGenerated 1 errors and 0 warnings")]
+fn implicit_defaults_are_classified(
+	#[case] args: &[&str],
+	#[case] default_features: bool,
+	#[case] code: i32,
+	#[case] want: &str,
+) {
+	let dir = fixture("defaults");
+	if !default_features {
+		let manifest = dir.path().join("root/Cargo.toml");
+		let raw = std::fs::read_to_string(&manifest).unwrap();
+		let raw = raw.replace("path = \"../dep\"", "path = \"../dep\", default-features = false");
+		std::fs::write(&manifest, raw).unwrap();
+	}
+	let lint = ["lint", "propagate-feature", "--feature", "std"];
+	let output = run(dir.path(), &[&lint, args].concat());
+	assert_eq!(output.status.code(), Some(code), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(stdout.contains(&format!("crate \"root\"\n  feature \"std\"\n{want}")), "{stdout}");
+}
+
+#[test]
+fn json_output_works() {
+	let dir = fixture("chain");
+	let output =
+		run(dir.path(), &["lint", "propagate-feature", "--feature", "std", "--format", "json"]);
+	assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+	let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert_eq!(json["feature_selection"], "all features");
+	let findings = json["findings"].as_array().expect("Findings are an array");
+	// Only `b` is checked against `c`, since `a` is only missing it after `b` got it.
+	let [finding] = findings.as_slice() else { panic!("Unexpected findings: {findings:?}") };
+	let finding = finding.as_object().expect("Findings are objects");
+
+	let keys = finding.keys().map(String::as_str).collect::<Vec<_>>();
+	assert_eq!(
+		keys,
+		["crate", "dependencies", "feature", "fixed", "kind", "known", "manifest_path", "name"]
+	);
+	let manifest = dir.path().canonicalize().unwrap().join("b/Cargo.toml");
+	assert!(finding["crate"].as_str().unwrap().contains("/b"), "{finding:?}");
+	assert_eq!(finding["name"], "b");
+	assert_eq!(finding["manifest_path"], manifest.display().to_string());
+	assert_eq!(finding["feature"], "std");
+	assert_eq!(finding["kind"], "missing-feature");
+	assert_eq!(finding["dependencies"], serde_json::json!(["c"]));
+	assert_eq!(finding["fixed"], false);
+	assert_eq!(finding["known"], false);
+}
+
+#[test]
+fn missing_features_are_fixed_along_the_chain() {
+	let dir = fixture("chain");
+	let lint = |args: &[&str]| {
+		run(dir.path(), &[&["lint", "propagate-feature", "--feature", "std"], args].concat())
+	};
+
+	// Only `c` has the feature, but `a` needs it as well once `b` has it.
+	let output = lint(&["--fix"]);
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(stdout.contains("fixed 2 issues."), "Unexpected output: {stdout}");
+	for (krate, dep) in [("a", "b"), ("b", "c")] {
+		let manifest = std::fs::read_to_string(dir.path().join(krate).join("Cargo.toml")).unwrap();
+		let want = format!("[features]\nstd = [\n\t\"{dep}/std\"\n]\n");
+		assert!(manifest.ends_with(&want), "Unexpected manifest of {krate}: {manifest}");
+	}
+
+	let output = lint(&[]);
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn multiple_features_are_linted_at_once() {
+	use std::{io::Write, process::Stdio};
+	let dir = fixture("kinds");
+	let mut cmd = Command::new(cargo_bin("feature"));
+	// Stdin can only be read once, so all features must share one metadata.
+	cmd.args(["lint", "propagate-feature", "--feature", "std,runtime-benchmarks", "-p", "root"]);
+	cmd.args(["--fix", "--metadata-file", "-"]);
+
+	let mut child = cmd
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.unwrap();
+	child.stdin.take().unwrap().write_all(&metadata(dir.path())).unwrap();
+	let output = child.wait_with_output().unwrap();
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	assert_eq!(output.status.code(), Some(0), "{stderr}");
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let want = "crate \"root\"\n  feature \"std\"\n    must propagate to:\n      dev\n      normal\n  feature \"runtime-benchmarks\"\n    must propagate to:\n      normal\nGenerated 2 errors and 0 warnings and fixed 3 issues.\n";
+	assert!(stdout.ends_with(want), "Unexpected output: {stdout}");
+	assert_eq!(stderr.matches("Wrote manifest").count(), 1, "Saved once: {stderr}");
+
+	let manifest = std::fs::read_to_string(dir.path().join("root/Cargo.toml")).unwrap();
+	let want = "std = [\n\t\"dev/std\",\n\t\"normal/std\"\n]\nruntime-benchmarks = [\n\t\"normal/runtime-benchmarks\"\n]\n";
+	assert!(manifest.ends_with(want), "Unexpected manifest: {manifest}");
+}
+
+#[test]
+fn crate_features_are_not_checked_features() {
+	let dir = fixture("chain");
+	let output = run(dir.path(), &["lint", "propagate-feature", "--features", "std"]);
+	assert_eq!(output.status.code(), Some(2));
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	assert!(stderr.contains("Pass the features to check with --feature std"), "{stderr}");
+}
+
+#[test]
+fn rules_are_checked_and_fixed() {
+	let dir = fixture("renamed");
+	let spec = dir.path().join("spec.toml");
+	std::fs::write(
+		&spec,
+		"test: std propagates\n\tgiven:\n\t\tcrates: [A, B]\n\t\tdependencies:\n\t\t\tA: direct: B\n\t\tfeatures:\n\t\t\tA: enabled: std\n\t\t\tB: defines: std\n\tthen:\n\t\tB: enabled: std\n\totherwise:\n\t\terror: \"std is not propagated\"\n\t\t\tauto-fix: enable-feature: A: std: B/std\n",
+	)
+	.unwrap();
+	let spec = spec.to_str().unwrap();
+	let lint =
+		|args: &[&str]| run(dir.path(), &[&["lint", "rules", "--spec", spec], args].concat());
+
+	let output = lint(&[]);
+	assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let want = "test \"std propagates\"\n  error: std is not propagated\n    A = root, B = dep-a\n    A = root, B = dep-b\n    A = root, B = dep-c\nGenerated 3 errors and fixed 0 issues.\n";
+	assert!(stdout.ends_with(want), "Unexpected output: {stdout}");
+
+	let output = lint(&["--fix"]);
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(stdout.ends_with("Generated 0 errors and fixed 3 issues.\n"), "{stdout}");
+	let manifest = std::fs::read_to_string(dir.path().join("root/Cargo.toml")).unwrap();
+	let want = "std = [\n\t\"codec/std\",\n\t\"opt?/std\",\n\t\"tgt/std\"\n]\n";
+	assert!(manifest.ends_with(want), "Unexpected manifest: {manifest}");
+
+	let output = lint(&[]);
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stdout));
+}
+
+/// Run the tool with `args` on the workspace `dir`.
+///
+/// The metadata cache ends up in the target directory of the workspace.
+fn run(dir: &Path, args: &[&str]) -> Output {
+	Command::new(cargo_bin("feature"))
+		.args(args)
+		.arg("--offline")
+		.arg("--manifest-path")
+		.arg(dir.join("Cargo.toml"))
+		.env_remove("CARGO_TARGET_DIR")
+		.output()
+		.unwrap()
+}
+
+/// Run `cargo metadata` in the workspace `dir` like the tool does by default.
+fn metadata(dir: &Path) -> Vec<u8> {
+	let output = Command::new(env!("CARGO"))
+		.args(["metadata", "--format-version", "1", "--all-features", "--offline"])
+		.current_dir(dir)
+		.output()
+		.unwrap();
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	output.stdout
+}
+
+/// Copy the fixture workspace `name` into a temporary directory.
+///
+/// Cargo writes a lockfile and fixes modify manifests, so the tests never run in place.
+fn fixture(name: &str) -> tempfile::TempDir {
+	let dir = tempfile::tempdir().unwrap();
+	copy_dir(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name), dir.path());
+	dir
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+	std::fs::create_dir_all(to).unwrap();
+
+	for entry in std::fs::read_dir(from).unwrap().map(Result::unwrap) {
+		let to = to.join(entry.file_name());
+		if entry.file_type().unwrap().is_dir() {
+			copy_dir(&entry.path(), &to);
+		} else {
+			std::fs::copy(entry.path(), to).unwrap();
+		}
+	}
+}